@@ -0,0 +1,131 @@
+use address_formatter::{Formatter, PlaceBuilder};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use include_dir::{include_dir, include_dir_impl};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// The raw components of an opencage test case
+type RawPlace = Vec<(String, String)>;
+
+/// read all the opencage test cases, grouped by country (the name of the test file)
+fn read_test_cases() -> BTreeMap<String, Vec<RawPlace>> {
+    let tests_dir = include_dir!("./address-formatting/testcases/countries");
+    let mut test_cases = BTreeMap::new();
+    for f in tests_dir.files() {
+        let country = f
+            .path()
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_uppercase();
+        let content = match f.contents_utf8() {
+            Some(c) => c,
+            None => continue,
+        };
+        let docs = YamlLoader::load_from_str(content)
+            .unwrap_or_else(|_| panic!("impossible to read test file {}", f.path().display()));
+        let places = test_cases.entry(country).or_insert_with(Vec::new);
+        places.extend(docs.iter().filter_map(read_raw_place));
+    }
+    test_cases
+}
+
+fn read_raw_place(yaml: &Yaml) -> Option<RawPlace> {
+    let components = yaml["components"].as_hash()?;
+    Some(
+        components
+            .iter()
+            .filter_map(|(k, v)| {
+                Some((
+                    k.as_str()?.to_owned(),
+                    v.as_str()
+                        .map(|s| s.to_string())
+                        .or_else(|| v.as_i64().map(|s| s.to_string()))?,
+                ))
+            })
+            .collect(),
+    )
+}
+
+fn to_place_input(raw: &RawPlace) -> impl Iterator<Item = (&str, String)> {
+    raw.iter().map(|(k, v)| (k.as_str(), v.clone()))
+}
+
+fn formatter_construction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("construction");
+    group.sample_size(10);
+    group.bench_function("Formatter::default", |b| b.iter(Formatter::default));
+    group.bench_function("Formatter::lazy", |b| b.iter(Formatter::lazy));
+    group.bench_function("PlaceBuilder::default", |b| b.iter(PlaceBuilder::default));
+    group.finish();
+}
+
+fn build_place(c: &mut Criterion) {
+    let test_cases = read_test_cases();
+    let all_places: Vec<_> = test_cases.values().flatten().collect();
+    let place_builder = PlaceBuilder::default();
+
+    let mut group = c.benchmark_group("build_place");
+    group.throughput(Throughput::Elements(all_places.len() as u64));
+    group.bench_function("all test cases", |b| {
+        b.iter(|| {
+            for p in &all_places {
+                place_builder.build_place(to_place_input(p));
+            }
+        })
+    });
+    group.finish();
+}
+
+fn format_by_country(c: &mut Criterion) {
+    let test_cases = read_test_cases();
+    let formatter = Formatter::default();
+    let place_builder = PlaceBuilder::default();
+
+    let mut group = c.benchmark_group("format");
+    group
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(1));
+    for (country, raw_places) in &test_cases {
+        let place = match raw_places.first() {
+            Some(p) => place_builder.build_place(to_place_input(p)),
+            None => continue,
+        };
+        group.bench_with_input(BenchmarkId::from_parameter(country), &place, |b, place| {
+            b.iter(|| formatter.format(place.clone()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn format_batch(c: &mut Criterion) {
+    let test_cases = read_test_cases();
+    let formatter = Formatter::default();
+    let place_builder = PlaceBuilder::default();
+    let places: Vec<_> = test_cases
+        .values()
+        .flatten()
+        .map(|p| place_builder.build_place(to_place_input(p)))
+        .collect();
+
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(places.len() as u64));
+    group.bench_function("format all test cases", |b| {
+        b.iter(|| {
+            for p in &places {
+                formatter.format(p.clone()).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    formatter_construction,
+    build_place,
+    format_by_country,
+    format_batch
+);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+//! Run the opencage test cases of a directory and print a conformance report
+//!
+//! `cargo run --example conformance -- [--json] [--expected-failures <file>] [--write-expected-failures <file>] <test cases directory>`
+use address_formatter::{run_conformance, ExpectedFailures, Formatter, PlaceBuilder};
+use failure::{format_err, Error};
+
+fn main() -> Result<(), Error> {
+    let mut json = false;
+    let mut expected_failures = ExpectedFailures::default();
+    let mut expected_failures_output = None;
+    let mut test_cases_dir = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--expected-failures" => {
+                let file = args
+                    .next()
+                    .ok_or_else(|| format_err!("no expected failures file given"))?;
+                expected_failures = ExpectedFailures::read(file)?;
+            }
+            "--write-expected-failures" => {
+                expected_failures_output = Some(
+                    args.next()
+                        .ok_or_else(|| format_err!("no expected failures file given"))?,
+                );
+            }
+            _ => test_cases_dir = Some(arg),
+        }
+    }
+    let test_cases_dir =
+        test_cases_dir.unwrap_or_else(|| "address-formatting/testcases/countries".to_owned());
+
+    let report = run_conformance(
+        &test_cases_dir,
+        &Formatter::default(),
+        &PlaceBuilder::default(),
+        &expected_failures,
+    )?;
+
+    if json {
+        println!("{}", report.to_json()?);
+    } else {
+        println!("{}", report);
+    }
+
+    if let Some(file) = expected_failures_output {
+        std::fs::write(file, ExpectedFailures::from_report(&report))?;
+    }
+
+    if !report.is_conform() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,13 @@
+#![no_main]
+use address_formatter_fuzz::{check_format, PLACE_BUILDER};
+use libfuzzer_sys::fuzz_target;
+
+// build a place from arbitrary keys and values, like an unstructured input (nominatim output for example)
+fuzz_target!(|data: Vec<(String, String)>| {
+    let build = || PLACE_BUILDER.build_place(data.iter().map(|(k, v)| (k.as_str(), v.clone())));
+
+    let text = check_format(build(), None);
+
+    // building the place again should give the same result
+    assert_eq!(text, check_format(build(), None));
+});
@@ -0,0 +1,25 @@
+#![no_main]
+use address_formatter::{Component, Place};
+use address_formatter_fuzz::check_format;
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use strum::IntoEnumIterator;
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// the values of the place, the index is used to find its component
+    values: Vec<(u8, String)>,
+    /// optionally force a country
+    country_code: Option<String>,
+}
+
+// build a place with arbitrary values on all its components
+fuzz_target!(|input: Input| {
+    let components: Vec<_> = Component::iter().collect();
+    let mut place = Place::default();
+    for (idx, value) in input.values {
+        place[components[idx as usize % components.len()]] = Some(value);
+    }
+
+    check_format(place, input.country_code);
+});
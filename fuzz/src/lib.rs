@@ -0,0 +1,60 @@
+//! Shared helpers for the fuzz targets
+
+use address_formatter::{Configuration, Formatter, Place, PlaceBuilder};
+
+lazy_static::lazy_static! {
+    pub static ref FORMATTER: Formatter = Formatter::default();
+    pub static ref PLACE_BUILDER: PlaceBuilder = PlaceBuilder::default();
+}
+
+/// Format the place and check all the invariants of the formatted text:
+///
+/// * the formatting does not fail
+/// * formatting the same place twice gives the same result
+/// * the text ends with exactly one newline
+/// * there are no empty lines (except for an empty place, formatted as a single newline)
+/// * no line has leading or trailing whitespaces
+pub fn check_format(place: Place, country_code: Option<String>) -> String {
+    let format = |p: Place| {
+        FORMATTER
+            .format_with_config(
+                p,
+                Configuration {
+                    country_code: country_code.clone(),
+                    ..Default::default()
+                },
+            )
+            .expect("impossible to format place")
+    };
+    let text = format(place.clone());
+
+    assert_eq!(text, format(place), "the formatting is not deterministic");
+    check_text(&text);
+    text
+}
+
+/// Check the invariants of a formatted text
+pub fn check_text(text: &str) {
+    // the String is valid utf8 by construction, but we check it in case some unsafe code is added
+    assert!(std::str::from_utf8(text.as_bytes()).is_ok());
+
+    assert!(text.ends_with('\n'), "no final newline in {:?}", text);
+    if text == "\n" {
+        return;
+    }
+    assert!(
+        !text.ends_with("\n\n"),
+        "several final newlines in {:?}",
+        text
+    );
+
+    for line in text.trim_end_matches('\n').split('\n') {
+        assert!(!line.is_empty(), "empty line in {:?}", text);
+        assert_eq!(
+            line,
+            line.trim(),
+            "leading or trailing whitespace in line {:?}",
+            line
+        );
+    }
+}
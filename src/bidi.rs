@@ -0,0 +1,95 @@
+use crate::{Component, Place};
+use serde::Serialize;
+use strum::IntoEnumIterator;
+use unicode_bidi::{bidi_class, BidiClass};
+
+const FIRST_STRONG_ISOLATE: char = '\u{2068}';
+const POP_DIRECTIONAL_ISOLATE: char = '\u{2069}';
+
+/// The languages written from right to left
+const RIGHT_TO_LEFT_LANGUAGES: [&str; 10] =
+    ["ar", "ckb", "dv", "fa", "he", "ps", "sd", "ug", "ur", "yi"];
+
+/// The direction of a text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextDirection {
+    /// like in latin, cyrillic or CJK scripts
+    LeftToRight,
+    /// like in arabic or hebrew scripts
+    RightToLeft,
+}
+
+impl TextDirection {
+    pub(crate) fn of_language(language: &str) -> Self {
+        if RIGHT_TO_LEFT_LANGUAGES.contains(&language) {
+            TextDirection::RightToLeft
+        } else {
+            TextDirection::LeftToRight
+        }
+    }
+
+    /// the direction of the first strong character of the text
+    pub(crate) fn of_text(text: &str) -> Option<Self> {
+        text.chars().find_map(|c| match bidi_class(c) {
+            BidiClass::L => Some(TextDirection::LeftToRight),
+            BidiClass::R | BidiClass::AL => Some(TextDirection::RightToLeft),
+            _ => None,
+        })
+    }
+}
+
+/// wrap in isolation marks the values of the components that could be reordered in a text of the direction of the country
+///
+/// the formatted text is isolated, so the cleanup and the postformat rules of the country
+/// are applied on the values without the marks
+pub(crate) fn isolate_components(text: &str, addr: &Place, direction: TextDirection) -> String {
+    // the country code is not rendered, it is kept as is to find the rules of the country
+    let mut values = Component::iter()
+        .filter(|c| *c != Component::CountryCode)
+        .filter_map(|c| addr[c].as_deref())
+        .filter(|v| !v.is_empty() && needs_isolation(v, direction))
+        .collect::<Vec<_>>();
+    // the longest values first, so a value is not isolated inside another one
+    values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+
+    // only the whole words are isolated (so the house number '1' is not isolated in the postcode '31000')
+    let is_boundary = |c: Option<char>| c.filter(|c| c.is_alphanumeric()).is_none();
+    let mut spans: Vec<(usize, usize)> = vec![];
+    for value in values {
+        for (start, _) in text.match_indices(value) {
+            let end = start + value.len();
+            if is_boundary(text[..start].chars().next_back())
+                && is_boundary(text[end..].chars().next())
+                && spans.iter().all(|(s, e)| end <= *s || start >= *e)
+            {
+                spans.push((start, end));
+            }
+        }
+    }
+    spans.sort_unstable();
+
+    let mut isolated = String::with_capacity(text.len());
+    let mut last = 0;
+    for (start, end) in spans {
+        isolated.push_str(&text[last..start]);
+        isolated.push(FIRST_STRONG_ISOLATE);
+        isolated.push_str(&text[start..end]);
+        isolated.push(POP_DIRECTIONAL_ISOLATE);
+        last = end;
+    }
+    isolated.push_str(&text[last..]);
+    isolated
+}
+
+fn needs_isolation(value: &str, direction: TextDirection) -> bool {
+    value.chars().any(|c| match (bidi_class(c), direction) {
+        (BidiClass::L, TextDirection::RightToLeft) => true,
+        (BidiClass::R, TextDirection::LeftToRight)
+        | (BidiClass::AL, TextDirection::LeftToRight) => true,
+        // the numbers are reordered with their neighbours in a right to left text
+        (BidiClass::EN, TextDirection::RightToLeft)
+        | (BidiClass::AN, TextDirection::RightToLeft) => true,
+        _ => false,
+    })
+}
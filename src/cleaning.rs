@@ -0,0 +1,700 @@
+//! Sanity cleaning of a [`Place`](../struct.Place.html) before its formatting
+//!
+//! The data of a place is not always reliable, so some cleaning steps are applied before the formatting,
+//! like removing the postcodes that look like a list of postcodes, or the values containing URLs.
+//!
+//! The steps are gathered in a [`CleaningPipeline`](struct.CleaningPipeline.html),
+//! that can be changed in the [`Configuration`](../struct.Configuration.html):
+//! some built-in steps can be removed, and some custom steps can be added.
+//!
+//! ```
+//! # #[macro_use] extern crate maplit;
+//! # fn main() {
+//! use address_formatter::{
+//!     CleaningPipeline, Component, Configuration, Formatter, PostcodeMaxLength,
+//! };
+//!
+//! // irish eircodes can have a long suffix, we accept longer postcodes and the URLs in the values
+//! let pipeline = CleaningPipeline::default()
+//!     .without(PostcodeMaxLength::NAME)
+//!     .without("url")
+//!     .with(PostcodeMaxLength(30));
+//!
+//! let mut place = hashmap!(
+//!     Component::Attention => "The Gallery - https://gallery.ie",
+//!     Component::Postcode => "D02 X285 (Apartment 12)",
+//!     Component::Road => "-",
+//! ).into();
+//! let changes = pipeline.apply(&mut place);
+//! // only the road without any word character has been removed
+//! assert_eq!(changes.len(), 1);
+//! assert_eq!(changes[0].step, "no_word_character");
+//! assert_eq!(changes[0].component, Component::Road);
+//! assert!(changes[0].is_removal());
+//!
+//! let conf = Configuration {
+//!     cleaning_pipeline: Some(pipeline),
+//!     ..Default::default()
+//! };
+//! let formatted = Formatter::lazy().format_with_config(place, conf).unwrap();
+//! assert!(formatted.contains("https://gallery.ie"));
+//! # }
+//! ```
+
+use crate::postal::remove_diacritics;
+use crate::{Component, Place};
+use itertools::Itertools;
+use regex::Regex;
+use serde::Serialize;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+use unicode_normalization::UnicodeNormalization as _;
+
+/// A step of the sanity cleaning, it can change or remove some values of a [`Place`](../struct.Place.html)
+pub trait CleaningStep: Send + Sync {
+    /// Name of the step, used to remove the step from a [`CleaningPipeline`](struct.CleaningPipeline.html)
+    /// and in the [`CleaningChange`](struct.CleaningChange.html)
+    fn name(&self) -> &str;
+
+    /// Clean the place
+    fn clean(&self, addr: &mut Place);
+}
+
+/// A change made by a [`CleaningStep`](trait.CleaningStep.html) on a component
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CleaningChange {
+    /// name of the step that made the change
+    pub step: String,
+    /// changed component
+    pub component: Component,
+    /// value of the component before the step
+    pub old_value: Option<String>,
+    /// value of the component after the step, `None` if the value has been removed
+    pub new_value: Option<String>,
+}
+
+impl CleaningChange {
+    /// true if the step removed the value of the component
+    pub fn is_removal(&self) -> bool {
+        self.new_value.is_none()
+    }
+}
+
+/// The ordered list of [`CleaningStep`](trait.CleaningStep.html) applied before the formatting.
+///
+/// The default pipeline contains the built-in steps, doing the same cleaning as the
+/// [perl implementation](https://github.com/OpenCageData/perl-Geo-Address-Formatter):
+/// * [`PostcodeMaxLength`](struct.PostcodeMaxLength.html) (`postcode_max_length`)
+/// * [`PostcodeList`](struct.PostcodeList.html) (`postcode_list`)
+/// * [`PostcodeRange`](struct.PostcodeRange.html) (`postcode_range`)
+/// * [`NoWordCharacter`](struct.NoWordCharacter.html) (`no_word_character`)
+/// * [`Url`](struct.Url.html) (`url`)
+#[derive(Clone)]
+pub struct CleaningPipeline {
+    steps: Vec<Arc<dyn CleaningStep>>,
+}
+
+impl Default for CleaningPipeline {
+    fn default() -> Self {
+        CleaningPipeline::empty()
+            .with(PostcodeMaxLength(20))
+            .with(PostcodeList)
+            .with(PostcodeRange)
+            .with(NoWordCharacter)
+            .with(Url)
+    }
+}
+
+impl std::fmt::Debug for CleaningPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.steps.iter().map(|s| s.name()))
+            .finish()
+    }
+}
+
+impl CleaningPipeline {
+    /// A pipeline without any step, nothing is cleaned
+    pub fn empty() -> Self {
+        CleaningPipeline { steps: vec![] }
+    }
+
+    /// Add a step at the end of the pipeline
+    pub fn with(mut self, step: impl CleaningStep + 'static) -> Self {
+        self.steps.push(Arc::new(step));
+        self
+    }
+
+    /// Remove all the steps with this name
+    pub fn without(mut self, name: &str) -> Self {
+        self.steps.retain(|s| s.name() != name);
+        self
+    }
+
+    /// The names of the steps, in the order they are applied
+    pub fn step_names(&self) -> impl Iterator<Item = &str> {
+        self.steps.iter().map(|s| s.name())
+    }
+
+    /// Apply all the steps on the place, and return the changes they made
+    pub fn apply(&self, addr: &mut Place) -> Vec<CleaningChange> {
+        let mut changes = vec![];
+        for step in &self.steps {
+            let before = addr.clone();
+            step.clean(addr);
+            for c in Component::iter() {
+                if before[c] != addr[c] {
+                    log::debug!(
+                        "cleaning step {} changed {} from {:?} to {:?}",
+                        step.name(),
+                        c,
+                        before[c],
+                        addr[c]
+                    );
+                    changes.push(CleaningChange {
+                        step: step.name().to_owned(),
+                        component: c,
+                        old_value: before[c].clone(),
+                        new_value: addr[c].clone(),
+                    });
+                }
+            }
+        }
+        changes
+    }
+}
+
+/// Remove the postcodes longer than the given number of characters
+#[derive(Debug, Clone)]
+pub struct PostcodeMaxLength(pub usize);
+
+impl PostcodeMaxLength {
+    /// name of the step
+    pub const NAME: &'static str = "postcode_max_length";
+}
+
+impl CleaningStep for PostcodeMaxLength {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        if let Some(post_code) = &addr[Component::Postcode] {
+            if post_code.chars().count() > self.0 {
+                addr[Component::Postcode] = None;
+            }
+        }
+    }
+}
+
+/// Remove the postcodes that are a list of postcodes, like `75001;75002`
+#[derive(Debug, Clone)]
+pub struct PostcodeList;
+
+impl PostcodeList {
+    /// name of the step
+    pub const NAME: &'static str = "postcode_list";
+}
+
+impl CleaningStep for PostcodeList {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        lazy_static::lazy_static! {
+            static ref POST_CODE_RANGE: Regex = Regex::new(r#"\d+;\d+"#).unwrap();
+        }
+        if let Some(post_code) = &addr[Component::Postcode] {
+            if POST_CODE_RANGE.is_match(post_code) {
+                addr[Component::Postcode] = None;
+            }
+        }
+    }
+}
+
+/// Keep only the first postcode of a range of postcodes, like `10001,10002`
+#[derive(Debug, Clone)]
+pub struct PostcodeRange;
+
+impl PostcodeRange {
+    /// name of the step
+    pub const NAME: &'static str = "postcode_range";
+}
+
+impl CleaningStep for PostcodeRange {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        lazy_static::lazy_static! {
+            static ref MATCHABLE_POST_CODE_RANGE: Regex = Regex::new(r#"^(\d{5}),\d{5}"#).unwrap();
+        }
+        if let Some(r) = addr[Component::Postcode]
+            .as_ref()
+            .and_then(|p| MATCHABLE_POST_CODE_RANGE.captures(p))
+            .and_then(|r| r.get(1))
+            .map(|c| c.as_str().to_owned())
+        {
+            addr[Component::Postcode] = Some(r);
+        }
+    }
+}
+
+/// Remove the values without any word character, like `-`
+#[derive(Debug, Clone)]
+pub struct NoWordCharacter;
+
+impl NoWordCharacter {
+    /// name of the step
+    pub const NAME: &'static str = "no_word_character";
+}
+
+impl CleaningStep for NoWordCharacter {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        lazy_static::lazy_static! {
+            static ref HAS_WORD: Regex = Regex::new(r"\w").unwrap();
+        }
+        remove_values(addr, |v| !HAS_WORD.is_match(v));
+    }
+}
+
+/// Remove the values containing an URL
+#[derive(Debug, Clone)]
+pub struct Url;
+
+impl Url {
+    /// name of the step
+    pub const NAME: &'static str = "url";
+}
+
+impl CleaningStep for Url {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        lazy_static::lazy_static! {
+            static ref IS_URL: Regex = Regex::new(r#"https?://"#).unwrap();
+        }
+        remove_values(addr, |v| IS_URL.is_match(v));
+    }
+}
+
+fn remove_values(addr: &mut Place, to_remove: impl Fn(&str) -> bool) {
+    for c in Component::iter() {
+        if let Some(v) = &addr[c] {
+            if to_remove(v) {
+                addr[c] = None;
+            }
+        }
+    }
+}
+
+/// Normalize the unicode of the values: the values are composed (NFC),
+/// the whitespaces (like the non-breaking spaces or the newlines) are replaced by a single space,
+/// and the control, zero width and bidi formatting characters are removed.
+///
+/// The values without any character left are removed.
+///
+/// ```
+/// # #[macro_use] extern crate maplit;
+/// # fn main() {
+/// use address_formatter::{CleaningPipeline, Component, UnicodeNormalization};
+///
+/// let mut place = hashmap!(
+///     Component::City => "Saint-E\u{301}tienne",
+///     Component::Road => "Rue\u{a0}de la\u{200b} Paix\n",
+///     Component::House => "\u{feff}",
+/// ).into();
+/// CleaningPipeline::empty().with(UnicodeNormalization).apply(&mut place);
+/// assert_eq!(place[Component::City], Some("Saint-Étienne".to_owned()));
+/// assert_eq!(place[Component::Road], Some("Rue de la Paix".to_owned()));
+/// assert_eq!(place[Component::House], None);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnicodeNormalization;
+
+impl UnicodeNormalization {
+    /// name of the step
+    pub const NAME: &'static str = "unicode_normalization";
+}
+
+impl CleaningStep for UnicodeNormalization {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        for c in Component::iter() {
+            if let Some(v) = &addr[c] {
+                let normalized = normalize_unicode(v);
+                if normalized != *v {
+                    addr[c] = Some(normalized).filter(|n| !n.is_empty());
+                }
+            }
+        }
+    }
+}
+
+fn normalize_unicode(value: &str) -> String {
+    let mut normalized = String::with_capacity(value.len());
+    for c in value.nfc() {
+        if c.is_whitespace() {
+            // the consecutive whitespaces are folded into one space
+            if !normalized.is_empty() && !normalized.ends_with(' ') {
+                normalized.push(' ');
+            }
+        } else if !c.is_control() && !is_invisible(c) {
+            normalized.push(c);
+        }
+    }
+    normalized.trim_end().to_owned()
+}
+
+/// the zero width and bidi formatting characters
+/// (the zero width joiners are kept, they are needed in some scripts and emojis)
+fn is_invisible(c: char) -> bool {
+    match c {
+        // soft hyphen, zero width space, left-to-right and right-to-left marks
+        '\u{ad}' | '\u{200b}' | '\u{200e}' | '\u{200f}' => true,
+        // bidi embeddings and overrides, word joiner and bidi isolates
+        '\u{202a}'..='\u{202e}' | '\u{2060}' | '\u{2066}'..='\u{2069}' => true,
+        // byte order mark (or zero width no-break space)
+        '\u{feff}' => true,
+        _ => false,
+    }
+}
+
+/// The areas compared by [`FuzzyDuplicates`](struct.FuzzyDuplicates.html), from the most important one
+const AREA_COMPONENTS: [Component; 11] = [
+    Component::City,
+    Component::Town,
+    Component::Village,
+    Component::Suburb,
+    Component::CityDistrict,
+    Component::Neighbourhood,
+    Component::County,
+    Component::StateDistrict,
+    Component::State,
+    Component::Region,
+    Component::Island,
+];
+
+/// The default prefixes of the administrative areas, ignored when comparing the areas
+const DEFAULT_ADMIN_PREFIXES: [&str; 16] = [
+    "bezirk",
+    "city of",
+    "comunidad de",
+    "county",
+    "departement d'",
+    "departement de",
+    "departement des",
+    "departement du",
+    "kreis",
+    "land",
+    "landkreis",
+    "municipality of",
+    "province de",
+    "province of",
+    "provincia di",
+    "region",
+];
+
+/// Remove the areas (like `County` or `State`) duplicating a more important area (like `City`),
+/// like the county "TOULOUSE" of the city "Toulouse", or the state "Land Berlin" of the city "Berlin".
+///
+/// The areas are compared without case and accents, and without their administrative prefixes
+/// (like "Land" or "Département de").
+/// With the containment, an area is also a duplicate if it contains the words of a more important area
+/// (or the opposite), like the city district "Paris 1er Arrondissement" of the city "Paris".
+/// It is disabled by default, as it also removes distinct areas, like the state "Kansas" of the city "Kansas City".
+///
+/// The step is not in the default [`CleaningPipeline`](struct.CleaningPipeline.html), it has to be added:
+///
+/// ```
+/// # #[macro_use] extern crate maplit;
+/// # fn main() {
+/// use address_formatter::{CleaningPipeline, Component, FuzzyDuplicates};
+///
+/// let pipeline = CleaningPipeline::default()
+///     .with(FuzzyDuplicates::default().with_admin_prefix("freistaat"));
+///
+/// let mut place = hashmap!(
+///     Component::City => "Dresden",
+///     Component::County => "DRESDEN",
+///     Component::Region => "Sachsen",
+///     Component::State => "Freistaat Sachsen",
+/// ).into();
+/// pipeline.apply(&mut place);
+/// assert_eq!(place[Component::County], None);
+/// assert_eq!(place[Component::State], Some("Freistaat Sachsen".to_owned()));
+/// assert_eq!(place[Component::Region], None);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FuzzyDuplicates {
+    /// the prefixes of the administrative areas ignored when comparing the areas (without case and accents)
+    pub admin_prefixes: Vec<String>,
+    /// an area containing the words of a more important area (or the opposite) is also a duplicate
+    /// (disabled by default)
+    pub containment: bool,
+}
+
+impl Default for FuzzyDuplicates {
+    fn default() -> Self {
+        FuzzyDuplicates {
+            admin_prefixes: DEFAULT_ADMIN_PREFIXES
+                .iter()
+                .map(|p| p.to_string())
+                .collect(),
+            containment: false,
+        }
+    }
+}
+
+impl FuzzyDuplicates {
+    /// name of the step
+    pub const NAME: &'static str = "fuzzy_duplicates";
+
+    /// Add a prefix of the administrative areas
+    pub fn with_admin_prefix(mut self, prefix: &str) -> Self {
+        self.admin_prefixes.push(comparison_key(prefix));
+        self
+    }
+
+    /// the words of the area, without case, accents and administrative prefix
+    fn words(&self, area: &str) -> Vec<String> {
+        let key = comparison_key(area);
+        let key = self
+            .admin_prefixes
+            .iter()
+            .filter_map(|p| {
+                let rest = key.strip_prefix(p.as_str())?;
+                // the prefix must be followed by a space (or end with an apostrophe, like "departement d'")
+                if p.ends_with('\'') {
+                    Some(rest)
+                } else {
+                    rest.strip_prefix(' ')
+                }
+            })
+            .filter(|rest| !rest.is_empty())
+            // the longest prefix is removed
+            .min_by_key(|rest| rest.len())
+            .unwrap_or(&key);
+        key.split_whitespace().map(|w| w.to_owned()).collect()
+    }
+
+    fn is_duplicate(&self, words: &[String], other_words: &[String]) -> bool {
+        words == other_words
+            || (self.containment
+                && (contains_words(words, other_words) || contains_words(other_words, words)))
+    }
+}
+
+impl CleaningStep for FuzzyDuplicates {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        let mut kept_areas: Vec<Vec<String>> = vec![];
+        for c in AREA_COMPONENTS.iter() {
+            let words = match &addr[*c] {
+                Some(v) => self.words(v),
+                None => continue,
+            };
+            if words.is_empty() {
+                continue;
+            }
+            if kept_areas
+                .iter()
+                .any(|kept| self.is_duplicate(&words, kept))
+            {
+                addr[*c] = None;
+            } else {
+                kept_areas.push(words);
+            }
+        }
+    }
+}
+
+fn comparison_key(value: &str) -> String {
+    remove_diacritics(value)
+        .to_lowercase()
+        .split_whitespace()
+        .join(" ")
+}
+
+/// true if the words contain all the other words, in the same order
+fn contains_words(words: &[String], other_words: &[String]) -> bool {
+    !other_words.is_empty()
+        && words
+            .windows(other_words.len())
+            .any(|window| window == other_words)
+}
+
+/// How to handle the components with several values, like `housenumber=12;14` or `road=Main Street;Second Street`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultiValuePolicy {
+    /// keep only the first value: `12;14` gives `12`
+    KeepFirst,
+    /// join the values with the separator of the country: `12;14` gives `12, 14`
+    Join,
+    /// join the values with the given separator
+    JoinWith(String),
+    /// render the numeric values as a range if they are written as a range (`12-14`)
+    /// or if they are consecutive (`12;13;14`, or `12;14` for the house numbers of the same side of a road),
+    /// the other values are joined with the separator of the country: `12;20` gives `12, 20`
+    Range,
+}
+
+/// Handle the [`Component`](../enum.Component.html)s `HouseNumber`, `Road` and `Postcode` with several values
+/// following a [`MultiValuePolicy`](enum.MultiValuePolicy.html).
+///
+/// The values are separated by a `;` (like in OpenStreetMap).
+/// The house numbers written as a range (like `12-14`) are also considered as multiple values,
+/// except in the countries where a dash is part of the house numbers (like in Japan).
+///
+/// ```
+/// use address_formatter::{CleaningPipeline, Component, MultiValuePolicy, MultiValues, Place};
+///
+/// let mut place = Place::default();
+/// place[Component::HouseNumber] = Some("12;14".to_owned());
+/// place[Component::Road] = Some("Main Street;Second Street".to_owned());
+/// CleaningPipeline::empty()
+///     .with(MultiValues(MultiValuePolicy::Range))
+///     .apply(&mut place);
+/// assert_eq!(place[Component::HouseNumber], Some("12–14".to_owned()));
+/// assert_eq!(place[Component::Road], Some("Main Street, Second Street".to_owned()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MultiValues(pub MultiValuePolicy);
+
+impl MultiValues {
+    /// name of the step
+    pub const NAME: &'static str = "multi_values";
+}
+
+impl CleaningStep for MultiValues {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clean(&self, addr: &mut Place) {
+        let country_code = addr[Component::CountryCode]
+            .as_ref()
+            .map(|c| c.to_uppercase())
+            .unwrap_or_default();
+        for c in &[Component::HouseNumber, Component::Road, Component::Postcode] {
+            let new_value = match &addr[*c] {
+                Some(v) => {
+                    let (values, is_range) = split_values(*c, v, &country_code);
+                    match values.as_slice() {
+                        [] => None,
+                        // only clean the value if it was written as several values, like '12;12'
+                        [_] if !v.contains(';') => continue,
+                        [value] => Some(value.to_string()),
+                        _ => Some(self.merge(*c, &values, is_range, &country_code)),
+                    }
+                }
+                None => continue,
+            };
+            addr[*c] = new_value;
+        }
+    }
+}
+
+impl MultiValues {
+    fn merge(
+        &self,
+        component: Component,
+        values: &[&str],
+        is_range: bool,
+        country_code: &str,
+    ) -> String {
+        match &self.0 {
+            MultiValuePolicy::KeepFirst => values[0].to_owned(),
+            MultiValuePolicy::Join => values.join(join_separator(country_code)),
+            MultiValuePolicy::JoinWith(separator) => values.join(separator),
+            MultiValuePolicy::Range => {
+                let numbers = values
+                    .iter()
+                    .map(|v| v.parse::<u64>().ok())
+                    .collect::<Option<Vec<_>>>();
+                match numbers {
+                    Some(numbers) if is_range || are_consecutive(component, &numbers) => {
+                        // the values are kept as written, to keep the leading zeros of the postcodes
+                        let first = (0..values.len()).min_by_key(|i| numbers[*i]);
+                        let last = (0..values.len()).max_by_key(|i| numbers[*i]);
+                        match (first, last) {
+                            (Some(first), Some(last)) => {
+                                format!("{}–{}", values[first], values[last])
+                            }
+                            _ => values.join(join_separator(country_code)),
+                        }
+                    }
+                    _ => values.join(join_separator(country_code)),
+                }
+            }
+        }
+    }
+}
+
+/// true if the numbers follow each other without gap
+/// (the house numbers of the same side of a road can be separated by 2)
+fn are_consecutive(component: Component, numbers: &[u64]) -> bool {
+    let mut sorted = numbers.to_vec();
+    sorted.sort_unstable();
+    let steps = sorted.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    steps.iter().all(|s| *s == 1)
+        || (component == Component::HouseNumber && steps.iter().all(|s| *s == 2))
+}
+
+/// the different values of a component, the duplicates are removed,
+/// and if the value is written as a range (like `12-14`)
+fn split_values<'a>(
+    component: Component,
+    value: &'a str,
+    country_code: &str,
+) -> (Vec<&'a str>, bool) {
+    lazy_static::lazy_static! {
+        static ref HOUSE_NUMBER_RANGE: Regex = Regex::new(r"^\s*(\d+)\s*-\s*(\d+)\s*$").unwrap();
+    }
+    // in those countries, the dash is part of the house number (like '1-2-3' in Japan or '37-12' in Queens)
+    const DASHED_HOUSE_NUMBERS: [&str; 2] = ["JP", "US"];
+
+    let mut values: Vec<&str> = vec![];
+    if component == Component::HouseNumber && !DASHED_HOUSE_NUMBERS.contains(&country_code) {
+        if let Some(range) = HOUSE_NUMBER_RANGE.captures(value) {
+            let (first, last) = (
+                range.get(1).unwrap().as_str(),
+                range.get(2).unwrap().as_str(),
+            );
+            // '14-12' is not a range
+            if first.parse::<u64>().ok() < last.parse::<u64>().ok() {
+                return (vec![first, last], true);
+            }
+        }
+    }
+    for v in value.split(';').map(|v| v.trim()).filter(|v| !v.is_empty()) {
+        if !values.contains(&v) {
+            values.push(v);
+        }
+    }
+    (values, false)
+}
+
+/// separator of the joined values of a country
+fn join_separator(country_code: &str) -> &'static str {
+    match country_code {
+        "CN" | "JP" | "TW" => "、",
+        _ => ", ",
+    }
+}
@@ -0,0 +1,381 @@
+//! Conformance runner for the [opencage test cases](https://github.com/OpenCageData/address-formatting/tree/master/testcases)
+//!
+//! It formats all the test cases of a directory and produces a report of the passing and failing tests by country.
+//!
+//! Some tests can be known to fail, they can be listed in an [`ExpectedFailures`](struct.ExpectedFailures.html)
+//! file to track them individually: the report is conform if all the failing tests are expected to fail,
+//! and if all tests expected to fail are still failing (to notice the fixed ones).
+//!
+//! ```no_run
+//! # fn main() -> Result<(), failure::Error> {
+//! use address_formatter::{run_conformance, ExpectedFailures, Formatter, PlaceBuilder};
+//!
+//! let report = run_conformance(
+//!     "address-formatting/testcases/countries",
+//!     &Formatter::lazy(),
+//!     &PlaceBuilder::default(),
+//!     &ExpectedFailures::read("tests/opencage_expected_failures.txt")?,
+//! )?;
+//! println!("{}", report);
+//! assert!(report.is_conform());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Formatter, PlaceBuilder};
+use failure::{format_err, Error, ResultExt};
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// An opencage test case
+#[derive(Debug, Clone, Serialize)]
+pub struct TestCase {
+    /// unique identifier of the test: `<file name>: <description>`
+    pub id: String,
+    /// country of the test (given by the name of the test file)
+    pub country: String,
+    /// description of the test
+    pub description: String,
+    /// raw components of the place to format
+    pub components: Vec<(String, String)>,
+    /// expected formatted text
+    pub expected: String,
+}
+
+/// Result of a [`TestCase`](struct.TestCase.html)
+#[derive(Debug, Clone, Serialize)]
+pub struct TestCaseResult {
+    /// identifier of the test
+    pub id: String,
+    /// country of the test
+    pub country: String,
+    /// expected formatted text
+    pub expected: String,
+    /// formatted text, None if the formatting failed
+    pub got: Option<String>,
+    /// error message if the formatting failed
+    pub error: Option<String>,
+    /// line diff between the expected and the formatted text, None if the test passed
+    pub diff: Option<String>,
+    /// true if the test is listed in the expected failures
+    pub expected_failure: bool,
+}
+
+impl TestCaseResult {
+    /// true if the formatted text is the expected one
+    pub fn passed(&self) -> bool {
+        self.got.as_ref() == Some(&self.expected)
+    }
+}
+
+/// Number of passing and failing tests of a country
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CountryReport {
+    /// number of passing tests
+    pub passed: usize,
+    /// number of failing tests
+    pub failed: usize,
+}
+
+/// Report of a conformance run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConformanceReport {
+    /// results of all the test cases
+    pub results: Vec<TestCaseResult>,
+    /// number of passing and failing tests by country
+    pub countries: BTreeMap<String, CountryReport>,
+    /// failing tests that are not in the expected failures
+    pub unexpected_failures: Vec<String>,
+    /// tests that are in the expected failures, but are passing
+    pub fixed: Vec<String>,
+}
+
+impl ConformanceReport {
+    /// true if the failing tests are exactly the expected failures
+    pub fn is_conform(&self) -> bool {
+        self.unexpected_failures.is_empty() && self.fixed.is_empty()
+    }
+
+    /// identifiers of all the failing tests
+    pub fn failures(&self) -> impl Iterator<Item = &str> {
+        self.results
+            .iter()
+            .filter(|r| !r.passed())
+            .map(|r| r.id.as_str())
+    }
+
+    /// JSON version of the report
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl std::fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for r in self.results.iter().filter(|r| !r.passed()) {
+            writeln!(f, "====================================")?;
+            writeln!(
+                f,
+                "{}{}",
+                r.id,
+                if r.expected_failure {
+                    " (expected failure)"
+                } else {
+                    ""
+                }
+            )?;
+            if let Some(e) = &r.error {
+                writeln!(f, "error: {}", e)?;
+            }
+            if let Some(d) = &r.diff {
+                writeln!(f, "{}", d)?;
+            }
+        }
+        writeln!(f, "====================================")?;
+        for (country, c) in &self.countries {
+            writeln!(
+                f,
+                "{:<10} {:>4} passed {:>4} failed",
+                country, c.passed, c.failed
+            )?;
+        }
+        let nb_failed = self.failures().count();
+        writeln!(
+            f,
+            "{} tests, {} passed, {} failed",
+            self.results.len(),
+            self.results.len() - nb_failed,
+            nb_failed
+        )?;
+        for id in &self.unexpected_failures {
+            writeln!(f, "unexpected failure: {}", id)?;
+        }
+        for id in &self.fixed {
+            writeln!(f, "fixed (to remove from the expected failures): {}", id)?;
+        }
+        Ok(())
+    }
+}
+
+/// List of the identifiers of the tests known to fail
+///
+/// In the file, there is one test identifier by line, empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct ExpectedFailures(BTreeSet<String>);
+
+impl ExpectedFailures {
+    /// read the expected failures from a file
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .with_context(|_| format!("impossible to read {}", path.as_ref().display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    /// read the expected failures from the content of a file
+    pub fn parse(content: &str) -> Self {
+        ExpectedFailures(
+            content
+                .lines()
+                .map(|l| l.trim())
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| l.to_owned())
+                .collect(),
+        )
+    }
+
+    /// true if the test is expected to fail
+    pub fn contains(&self, id: &str) -> bool {
+        self.0.contains(id)
+    }
+
+    /// content of the file listing all the failures of a report
+    pub fn from_report(report: &ConformanceReport) -> String {
+        let mut content = "# opencage test cases known to fail, one test by line\n".to_owned();
+        for id in report.failures().sorted() {
+            content.push_str(id);
+            content.push('\n');
+        }
+        content
+    }
+}
+
+/// read all the test cases of the yaml files of a directory
+pub fn read_test_cases(dir: impl AsRef<Path>) -> Result<Vec<TestCase>, Error> {
+    let dir = dir.as_ref();
+    let mut files = std::fs::read_dir(dir)
+        .with_context(|_| format!("impossible to read directory {}", dir.display()))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|p| p.extension() == Some(std::ffi::OsStr::new("yaml")));
+    files.sort();
+
+    let mut test_cases = vec![];
+    for path in files {
+        let file_name = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default()
+            .to_owned();
+        let country = path
+            .file_stem()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default()
+            .to_uppercase();
+        let content = std::fs::read_to_string(&path)
+            .with_context(|_| format!("impossible to read test file {}", path.display()))?;
+        let docs = YamlLoader::load_from_str(&content)
+            .with_context(|_| format!("impossible to read test file {}", path.display()))?;
+
+        let mut ids = BTreeMap::<String, usize>::new();
+        for doc in docs {
+            let description = doc["description"]
+                .as_str()
+                .unwrap_or("no description provided")
+                .to_owned();
+            // the description is used as identifier, it needs to be unique in the file
+            let nb_seen = ids
+                .entry(description.clone())
+                .and_modify(|n| *n += 1)
+                .or_insert(1);
+            let id = if *nb_seen == 1 {
+                format!("{}: {}", file_name, description)
+            } else {
+                format!("{}: {} #{}", file_name, description, nb_seen)
+            };
+            let expected = doc["expected"]
+                .as_str()
+                .ok_or_else(|| format_err!("no expected value provided for {}", id))?
+                .to_owned();
+            let components = read_components(&doc["components"])
+                .ok_or_else(|| format_err!("no component value provided for {}", id))?;
+
+            test_cases.push(TestCase {
+                id,
+                country: country.clone(),
+                description,
+                components,
+                expected,
+            });
+        }
+    }
+    Ok(test_cases)
+}
+
+// unfortunalty, at the time of writing, serde_yaml does not handle multiple documents in a yaml,
+// so we have to parse the parse manually
+fn read_components(yaml: &Yaml) -> Option<Vec<(String, String)>> {
+    Some(
+        yaml.as_hash()?
+            .iter()
+            .filter_map(|(k, v)| {
+                Some((
+                    k.as_str()?.to_owned(),
+                    v.as_str()
+                        .map(|s| s.to_string())
+                        .or_else(|| v.as_i64().map(|s| s.to_string()))?,
+                ))
+            })
+            .collect(),
+    )
+}
+
+/// run a [`TestCase`](struct.TestCase.html)
+pub fn run_test_case(
+    test_case: &TestCase,
+    formatter: &Formatter,
+    place_builder: &PlaceBuilder,
+) -> TestCaseResult {
+    let place = place_builder.build_place(
+        test_case
+            .components
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone())),
+    );
+    let (got, error) = match formatter.format(place) {
+        Ok(t) => (Some(t), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let diff = match &got {
+        Some(got) if *got == test_case.expected => None,
+        Some(got) => Some(diff(&test_case.expected, got)),
+        None => Some(diff(&test_case.expected, "")),
+    };
+    TestCaseResult {
+        id: test_case.id.clone(),
+        country: test_case.country.clone(),
+        expected: test_case.expected.clone(),
+        got,
+        error,
+        diff,
+        expected_failure: false,
+    }
+}
+
+/// run all the test cases of a directory and build the [`ConformanceReport`](struct.ConformanceReport.html)
+pub fn run(
+    test_cases_dir: impl AsRef<Path>,
+    formatter: &Formatter,
+    place_builder: &PlaceBuilder,
+    expected_failures: &ExpectedFailures,
+) -> Result<ConformanceReport, Error> {
+    let test_cases = read_test_cases(test_cases_dir)?;
+
+    let mut report = ConformanceReport::default();
+    for test_case in &test_cases {
+        let mut result = run_test_case(test_case, formatter, place_builder);
+        result.expected_failure = expected_failures.contains(&result.id);
+
+        let country_report = report.countries.entry(result.country.clone()).or_default();
+        if result.passed() {
+            country_report.passed += 1;
+            if result.expected_failure {
+                report.fixed.push(result.id.clone());
+            }
+        } else {
+            country_report.failed += 1;
+            if !result.expected_failure {
+                report.unexpected_failures.push(result.id.clone());
+            }
+        }
+        report.results.push(result);
+    }
+    Ok(report)
+}
+
+/// simple line diff, the expected lines are prefixed by `-` and the formatted lines by `+`
+fn diff(expected: &str, got: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let got: Vec<_> = got.lines().collect();
+
+    // longest common subsequence of the lines
+    let mut lcs = vec![vec![0; got.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..got.len()).rev() {
+            lcs[i][j] = if expected[i] == got[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut res = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < got.len() {
+        if i < expected.len() && j < got.len() && expected[i] == got[j] {
+            res.push(format!(" {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == got.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            res.push(format!("-{}", expected[i]));
+            i += 1;
+        } else {
+            res.push(format!("+{}", got[j]));
+            j += 1;
+        }
+    }
+    res.join("\n")
+}
@@ -0,0 +1,64 @@
+use crate::formatter::CountryCode;
+use crate::{Component, Place};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+lazy_static::lazy_static! {
+    static ref COUNTRY_NAMES: HashMap<String, HashMap<String, String>> =
+        serde_yaml::from_str(include_str!("../data/country_names.yaml")).expect("invalid country_names.yaml file");
+}
+
+/// The name of a country in a language.
+///
+/// The names come from the [CLDR](https://cldr.unicode.org).
+/// If the language has a region (like "de-AT"), the name of its primary language is used.
+///
+/// ```
+/// use address_formatter::country_name;
+///
+/// assert_eq!(country_name("DE", "fr"), Some("Allemagne"));
+/// assert_eq!(country_name("gb", "en"), Some("United Kingdom"));
+/// assert_eq!(country_name("FR", "de-AT"), Some("Frankreich"));
+/// assert_eq!(country_name("JP", "ja"), Some("日本"));
+/// assert_eq!(country_name("DE", "tlh"), None);
+/// ```
+pub fn country_name(country_code: &str, language: &str) -> Option<&'static str> {
+    let country_code = CountryCode::from_str(country_code).ok()?;
+    get_by_language(&COUNTRY_NAMES, language)?
+        .get(country_code.as_str())
+        .map(|n| n.as_str())
+}
+
+/// get the value of a language, or of its primary language if the language has a region (like "de-AT")
+pub(crate) fn get_by_language<'a, T>(
+    by_language: &'a HashMap<String, T>,
+    language: &str,
+) -> Option<&'a T> {
+    let language = language.to_lowercase();
+    by_language.get(&language).or_else(|| {
+        language
+            .split(['-', '_'])
+            .next()
+            .and_then(|primary_language| by_language.get(primary_language))
+    })
+}
+
+/// translate the country of the place, the country is kept as is if its name is not known
+///
+/// the country code is the one of the place before the country overrides
+/// (like GF for French Guiana, that uses the configuration of FR)
+pub(crate) fn localize_country(
+    addr: &mut Place,
+    country_code: &Option<CountryCode>,
+    language: &str,
+) {
+    if addr[Component::Country].is_none() {
+        return;
+    }
+    if let Some(name) = country_code
+        .as_ref()
+        .and_then(|c| country_name(c.as_str(), language))
+    {
+        addr[Component::Country] = Some(name.to_owned());
+    }
+}
@@ -1,27 +1,48 @@
+use crate::bidi::{isolate_components, TextDirection};
+use crate::cleaning::{
+    CleaningChange, CleaningPipeline, MultiValuePolicy, MultiValues, UnicodeNormalization,
+};
+use crate::country::{country_name, localize_country};
+use crate::postal::{apply_postal_label_rules, split_lines, FormattedLine};
+use crate::postcode::{normalize_postcode, uses_postcodes};
+use crate::region::RegionNames;
+use crate::replacement::Replacement;
+use crate::report::{ComponentRewrite, FormatReport};
+use crate::title_case::title_case_place;
 use crate::{Component, Place};
 use failure::Fail;
 use failure::{format_err, Error};
 use itertools::Itertools;
+use once_cell::sync::OnceCell;
 use regex::{Regex, RegexBuilder};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::Arc;
 use strum::IntoEnumIterator;
+use unicode_script::{Script, UnicodeScript};
 
 const TEMPLATE_NAME: &'static str = "addr";
 
-/// Represents a Regex and the value to replace the regex matches with
-#[derive(Debug, Clone)]
-pub(crate) struct Replacement {
-    pub regex: regex::Regex,
-    pub replacement_value: String,
-}
+/// the components used to detect the script of a place
+const SCRIPT_COMPONENTS: [Component; 8] = [
+    Component::Road,
+    Component::Neighbourhood,
+    Component::Suburb,
+    Component::CityDistrict,
+    Component::City,
+    Component::Town,
+    Component::Village,
+    Component::State,
+];
 
 /// Replacement rule
 /// a Replacement can be on all fields, or only one of them
+/// (with the raw value to replace, since a component exactly equal to this value is replaced)
 #[derive(Debug, Clone)]
 pub(crate) enum ReplaceRule {
     All(Replacement),
-    Component((Component, Replacement)),
+    Component((Component, String, Replacement)),
 }
 
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
@@ -31,7 +52,7 @@ impl FromStr for CountryCode {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() == 2 {
+        if s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()) {
             if s == "UK" {
                 Ok(CountryCode("GB".to_owned()))
             } else {
@@ -65,30 +86,81 @@ pub(crate) struct NewComponent {
     pub new_value: String,
 }
 
+/// the components of the placeholders (like `{{{road}}}`) of a part of a template
+fn placeholders(text: &str) -> impl Iterator<Item = Component> + '_ {
+    text.split("{{{")
+        .skip(1)
+        .filter_map(|placeholder| placeholder.split("}}}").next())
+        .filter_map(|name| Component::from_str(name.trim()).ok())
+}
+
 /// The template handle the handlerbar template used to format a [`Place`](struct.Place.html)
+///
+/// The handlebar template is compiled only on first use
 #[derive(Debug, Default)]
 pub(crate) struct Template {
     /// Moustache template
-    place_template: String, // used only to clone the template
-    pub handlebar_handler: handlebars::Handlebars,
+    place_template: String,
+    handlebar_handler: OnceCell<handlebars::Handlebars>,
+    line_components: OnceCell<Vec<Vec<Component>>>,
 }
 
 impl Template {
     pub fn new(place_template: &str) -> Self {
-        let mut template_engine = crate::handlebar_helper::new_template_engine();
-        template_engine
-            .register_template_string(TEMPLATE_NAME, place_template)
-            .expect("impossible to build template");
         Template {
             place_template: place_template.to_owned(),
-            handlebar_handler: template_engine,
+            handlebar_handler: OnceCell::new(),
+            line_components: OnceCell::new(),
         }
     }
-}
 
-impl Clone for Template {
-    fn clone(&self) -> Self {
-        Self::new(self.place_template.as_str())
+    /// Get the components of each line of the template, from their placeholders (like `{{{road}}}`)
+    /// the lines without any component are skipped
+    pub fn line_components(&self) -> &[Vec<Component>] {
+        self.line_components.get_or_init(|| {
+            self.place_template
+                .lines()
+                .map(|line| placeholders(line).collect::<Vec<_>>())
+                .filter(|components| !components.is_empty())
+                .collect()
+        })
+    }
+
+    /// Get the components of the place rendered by the template, from its placeholders
+    /// (only the first alternative with a value of a `{{#first}}` block is rendered)
+    pub fn used_components(&self, addr: &Place) -> HashSet<Component> {
+        let mut blocks = self.place_template.split("{{#first}}");
+        let mut used: HashSet<Component> = blocks
+            .next()
+            .map(placeholders)
+            .into_iter()
+            .flatten()
+            .collect();
+        for block in blocks {
+            let mut parts = block.splitn(2, "{{/first}}");
+            let alternatives = parts.next().unwrap_or_default();
+            let after_block = parts.next().unwrap_or_default();
+            used.extend(
+                alternatives
+                    .split("||")
+                    .map(|alternative| placeholders(alternative).collect::<Vec<_>>())
+                    .find(|components| components.iter().any(|c| addr[*c].is_some()))
+                    .unwrap_or_default(),
+            );
+            used.extend(placeholders(after_block));
+        }
+        used
+    }
+
+    /// Get the handlebar template, compile it if it has not already been done
+    pub fn handlebar_handler(&self) -> Result<&handlebars::Handlebars, Error> {
+        self.handlebar_handler.get_or_try_init(|| {
+            let mut template_engine = crate::handlebar_helper::new_template_engine();
+            template_engine
+                .register_template_string(TEMPLATE_NAME, &self.place_template)
+                .map_err(|e| e.context("impossible to build template"))?;
+            Ok(template_engine)
+        })
     }
 }
 
@@ -99,21 +171,243 @@ impl Clone for Template {
 #[derive(Debug, Default, Clone)]
 pub(crate) struct Rules {
     pub replace: Vec<ReplaceRule>,
-    pub postformat_replace: Vec<Replacement>,
+    pub postformat_replace: Vec<ReplaceRule>,
+}
+
+/// Replacement rules as written in the configuration, a list of `[<regex>, <replacement value>]`
+pub(crate) type RawReplaceRules = Vec<(String, String)>;
+
+/// The raw [`Rules`](struct.Rules.html) of a country, compiled only on first use
+#[derive(Debug, Default)]
+pub(crate) struct LazyRules {
+    replace: RawReplaceRules,
+    postformat_replace: RawReplaceRules,
+    rules: OnceCell<Rules>,
+}
+
+impl LazyRules {
+    pub fn new(replace: RawReplaceRules, postformat_replace: RawReplaceRules) -> Self {
+        LazyRules {
+            replace,
+            postformat_replace,
+            rules: OnceCell::new(),
+        }
+    }
+
+    /// Get the compiled rules, compile them if it has not already been done
+    pub fn rules(&self) -> Result<&Rules, Error> {
+        self.rules.get_or_try_init(|| {
+            crate::read_configuration::build_rules(&self.replace, &self.postformat_replace)
+        })
+    }
+}
+
+/// Some countries use the template and the rules of another country (they are marked as `use_country` in the configuration),
+/// with some slight changes.
+///
+/// The template and the rules are shared with the other country, only those changes are stored by country.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct CountryOverride {
+    /// Override the country
     pub change_country: Option<String>,
+    /// Override the country code (with the code of the country whose template is used)
     pub change_country_code: Option<String>,
-    /// Override the country
+    /// Add a new component to the place
     pub add_component: Option<NewComponent>,
 }
 
+impl CountryOverride {
+    fn apply(&self, addr: &mut Place) {
+        lazy_static::lazy_static! {
+            static ref COMPONENT_VARIABLE: Regex = Regex::new(r"\$(\w*)").unwrap();
+        }
+        if let Some(change_country) = &self.change_country {
+            // the new country can use another component, like '$state, United Kingdom'
+            let new_country = match COMPONENT_VARIABLE.captures(change_country) {
+                Some(cap) => {
+                    let value = Component::from_str(&cap[1])
+                        .ok()
+                        .and_then(|c| addr[c].clone())
+                        .unwrap_or_default();
+                    change_country.replacen(&cap[0], &value, 1)
+                }
+                None => change_country.clone(),
+            };
+            addr[Component::Country] = Some(new_country);
+        }
+        if let Some(add_component) = &self.add_component {
+            addr[add_component.component] = Some(add_component.new_value.clone());
+        }
+        if let Some(change_country_code) = &self.change_country_code {
+            addr[Component::CountryCode] = Some(change_country_code.clone());
+        }
+    }
+}
+
+/// The components a [`Place`](struct.Place.html) needs to be formatted with the main template of its country.
+///
+/// The [`Place`](struct.Place.html) needs all the components of at least one of the sets,
+/// else the fallback template of the country is used.
+///
+/// The default rules need a `Road` or a `Postcode`, the countries without postcode system
+/// also accept a `House` in a locality, and some countries have specific rules
+/// (defined in [data/minimum_components.yaml](https://github.com/CanalTP/address-formatter-rs/blob/master/data/minimum_components.yaml)).
+///
+/// ```
+/// # use address_formatter::{Component, MinimumComponents, Place};
+/// let minimum_components = MinimumComponents::new(vec![
+///     vec![Component::Road],
+///     vec![Component::House, Component::Village],
+/// ]);
+/// let mut place = Place::default();
+/// place[Component::House] = Some("Cnoc na Greine".to_owned());
+/// assert!(!minimum_components.is_satisfied_by(&place));
+/// place[Component::Village] = Some("Ballyvourney".to_owned());
+/// assert!(minimum_components.is_satisfied_by(&place));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinimumComponents(Vec<Vec<Component>>);
+
+impl MinimumComponents {
+    /// Build the rules from the sets of components
+    pub fn new(sets: Vec<Vec<Component>>) -> Self {
+        MinimumComponents(sets)
+    }
+
+    /// The sets of components, a place needs all the components of one of them
+    pub fn sets(&self) -> &[Vec<Component>] {
+        &self.0
+    }
+
+    /// Check if the [`Place`](struct.Place.html) has enough components for the main template
+    pub fn is_satisfied_by(&self, addr: &Place) -> bool {
+        self.0
+            .iter()
+            .any(|set| set.iter().all(|c| addr[*c].is_some()))
+    }
+}
+
+impl Default for MinimumComponents {
+    /// if there are neither 'road' nor 'postcode', we consider that there are not enough data
+    /// and use the fallback template
+    fn default() -> Self {
+        MinimumComponents(vec![vec![Component::Road], vec![Component::Postcode]])
+    }
+}
+
+/// The kind of template used to format a [`Place`](struct.Place.html)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TemplateKind {
+    /// The main template of the country, used when the place has enough components
+    Main,
+    /// The fallback template of the country, used when the place does not have enough components
+    Fallback,
+}
+
+/// The script of the template used to format a [`Place`](struct.Place.html)
+///
+/// Some countries (like Japan, China or Korea) write the places from the country to the house number
+/// in their native script, and from the house number to the country in the latin script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TemplateScript {
+    /// The template of the country for its native script
+    Native,
+    /// The template of the country for the latin script, if the country has a specific one
+    Latin,
+}
+
+/// The abbreviations of a language, like "Avenue" to "Ave" in the roads in english.
+///
+/// The regexes are compiled only on first use
+#[derive(Debug, Default)]
+pub(crate) struct Abbreviations {
+    /// for each component, the abbreviated words (long form to short form)
+    words: Vec<(Component, HashMap<String, String>)>,
+    regexes: OnceCell<Vec<(Component, Regex)>>,
+}
+
+impl Abbreviations {
+    pub fn new(words: Vec<(Component, HashMap<String, String>)>) -> Self {
+        Abbreviations {
+            words,
+            regexes: OnceCell::new(),
+        }
+    }
+
+    /// Get the compiled regexes, compile them if it has not already been done
+    /// there is one regex by component, matching all its words
+    fn regexes(&self) -> Result<&Vec<(Component, Regex)>, Error> {
+        self.regexes.get_or_try_init(|| {
+            self.words
+                .iter()
+                .map(|(component, words)| {
+                    // the longest words first, so the longest match is used
+                    let pattern = words
+                        .keys()
+                        .sorted_by_key(|w| std::cmp::Reverse(w.len()))
+                        .map(|w| regex::escape(w))
+                        .join("|");
+                    Regex::new(&format!(r"(^|\s)({})\b", pattern))
+                        .map(|r| (*component, r))
+                        .map_err(|e| format_err!("invalid abbreviations for {}: {}", component, e))
+                })
+                .collect()
+        })
+    }
+
+    /// abbreviate all the words of the place's components
+    fn apply(&self, addr: &mut Place) -> Result<(), Error> {
+        for ((component, words), (_, regex)) in self.words.iter().zip(self.regexes()?) {
+            if let Some(value) = &addr[*component] {
+                let abbreviated = regex.replace_all(value, |caps: &regex::Captures| {
+                    format!("{}{}", &caps[1], words[&caps[2]])
+                });
+                if let std::borrow::Cow::Owned(abbreviated) = abbreviated {
+                    addr[*component] = Some(abbreviated);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Templates {
     pub default_template: Template,
     pub fallback_template: Template,
-    pub templates_by_country: HashMap<CountryCode, Template>,
-    pub rules_by_country: HashMap<CountryCode, Rules>,
-    pub fallback_templates_by_country: HashMap<CountryCode, Template>,
-    pub fallback_rules: Rules,
+    pub templates_by_country: HashMap<CountryCode, Arc<Template>>,
+    pub rules_by_country: HashMap<CountryCode, Arc<LazyRules>>,
+    pub overrides_by_country: HashMap<CountryCode, CountryOverride>,
+    pub fallback_templates_by_country: HashMap<CountryCode, Arc<Template>>,
+    pub fallback_rules: LazyRules,
+    pub default_minimum_components: MinimumComponents,
+    pub minimum_components_by_country: HashMap<CountryCode, MinimumComponents>,
+    pub latin_templates_by_country: HashMap<CountryCode, Arc<Template>>,
+}
+
+impl Templates {
+    /// Compile all the templates and all the rules
+    fn compile_all(&self) -> Result<(), Error> {
+        self.default_template.handlebar_handler()?;
+        self.fallback_template.handlebar_handler()?;
+        self.fallback_rules.rules()?;
+        let templates = self
+            .templates_by_country
+            .iter()
+            .chain(self.fallback_templates_by_country.iter())
+            .chain(self.latin_templates_by_country.iter());
+        for (country_code, template) in templates {
+            template
+                .handlebar_handler()
+                .map_err(|e| e.context(format!("invalid template for {}", country_code)))?;
+        }
+        for (country_code, rules) in &self.rules_by_country {
+            rules
+                .rules()
+                .map_err(|e| e.context(format!("invalid rules for {}", country_code)))?;
+        }
+        Ok(())
+    }
 }
 
 /// This [`Formatter`](struct.Formatter.html) holds all the configuration needed to format a [`Place`](struct.Place.html)
@@ -126,7 +420,7 @@ pub(crate) struct Templates {
 /// # #[macro_use] extern crate maplit;
 /// # fn main() {
 ///    use address_formatter::Component::*;
-///    let formatter = address_formatter::Formatter::default();
+///    let formatter = address_formatter::Formatter::lazy();
 ///
 ///    let addr: address_formatter::Place = hashmap!(
 ///        City => "Toulouse",
@@ -152,38 +446,146 @@ pub(crate) struct Templates {
 /// # }
 ///
 /// ```
+///
+/// All the templates and rules are compiled when the [`Formatter`](struct.Formatter.html) is built.
+/// If only some countries are formatted, [`Formatter::lazy`](struct.Formatter.html#method.lazy)
+/// can be used instead to compile the templates and rules of a country only when it is first needed.
 pub struct Formatter {
     pub(crate) templates: Templates,
     pub(crate) county_codes: HashMap<(CountryCode, String), String>,
     pub(crate) state_codes: HashMap<(CountryCode, String), String>,
-    // country_to_lang: Vec<>,
-    // abbreviations: Vec<>,
+    pub(crate) country_to_lang: HashMap<CountryCode, Vec<String>>,
+    pub(crate) abbreviations: HashMap<String, Abbreviations>,
     // valid_replacement_components: Vec<>
 }
 
 /// This configuration changes the [`Formatter`](struct.Formatter.html) behavior
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Configuration {
     /// force the use of a give country (so the [`Place`](struct.Place.html) country_code is not used)
     pub country_code: Option<String>,
     /// use abbreviation in the formated text (like "Avenue" to "Av.")
     pub abbreviate: Option<bool>,
+    /// write the roads and the areas in title case when they are all in uppercase or all in lowercase
+    /// (like "RUE DE LA PAIX"), with the particles of the language of the place (like "Rue de la Paix"),
+    /// guessed among the languages of the country from the particles found in the place.
+    /// The codes (like the state "NY") and the road references (like "US 101" or "D113") are kept in uppercase
+    /// (see [`title_case`](fn.title_case.html))
+    pub title_case: Option<bool>,
+    /// override the components needed to use the main template of the country
+    /// (see [`MinimumComponents`](struct.MinimumComponents.html))
+    pub minimum_components: Option<MinimumComponents>,
+    /// force the use of the main or the fallback template of the country,
+    /// whatever the components of the [`Place`](struct.Place.html)
+    pub template_kind: Option<TemplateKind>,
+    /// force the script of the template (by default the latin template is used if the main components of
+    /// the place are written in the latin script)
+    pub template_script: Option<TemplateScript>,
+    /// normalize the unicode of the values before the cleaning
+    /// (see [`UnicodeNormalization`](struct.UnicodeNormalization.html)), true by default
+    pub normalize_input: Option<bool>,
+    /// the sanity cleaning steps applied on the [`Place`](struct.Place.html) before the formatting
+    /// (by default the [built-in steps](struct.CleaningPipeline.html))
+    pub cleaning_pipeline: Option<CleaningPipeline>,
+    /// how to handle the house numbers, roads and postcodes with several values (like `12;14`),
+    /// applied before the cleaning pipeline (by default they are kept as is)
+    pub multi_value_policy: Option<MultiValuePolicy>,
+    /// format the place for a mail label, following the rules of the postal operator of the country
+    /// (like the uppercase locality line in France, or the uppercase text without punctuation in the US)
+    pub postal_label: Option<bool>,
+    /// wrap the components in unicode isolation marks (FSI and PDI) when they could be reordered in the
+    /// direction of the language of the country (like the latin words or the numbers in an arabic place,
+    /// or the hebrew words in a french place)
+    pub isolate_bidi: Option<bool>,
+    /// the country the mail is sent from.
+    /// If it is the country of the [`Place`](struct.Place.html) (domestic mail), the country line is removed,
+    /// else (international mail) the country is written on the last line, in uppercase,
+    /// in the output language if set, else in french if it is a language of the origin country,
+    /// else in english (following the UPU conventions)
+    pub origin_country: Option<String>,
+    /// the language of the country name (like "de" to print "Frankreich"), the country is printed as given if
+    /// its name is not known in this language (see [`country_name`](fn.country_name.html))
+    pub output_language: Option<String>,
+    /// write the place in the latin script (like "Moskva" instead of "Москва"), with the latin order template
+    /// of the country (like in Japan, where the native template starts with the country)
+    /// and the english name of the country (if no output language is set)
+    ///
+    /// The kanji of the japanese places are not supported: their reading cannot be found from the characters,
+    /// so they are kept as is (and reported in the [`FormatReport`](struct.FormatReport.html))
+    #[cfg(feature = "transliteration")]
+    pub transliterate: Option<bool>,
+    /// the names of the states in the output language
+    /// (by default the [built-in table](struct.RegionNames.html))
+    pub region_names: Option<RegionNames>,
+    /// normalize the postcode to the official format of its country (like "sw1a1aa" to "SW1A 1AA" in the UK)
+    /// (see [`normalize_postcode`](fn.normalize_postcode.html))
+    pub normalize_postcodes: Option<bool>,
+    /// when the postcodes are normalized, remove the postcodes that are not valid for their country
+    pub drop_invalid_postcodes: Option<bool>,
+    /// remove the postcodes of the countries without postcode system
+    /// (see [`uses_postcodes`](fn.uses_postcodes.html))
+    pub drop_postcodes_without_postal_system: Option<bool>,
 }
 
 impl Default for Formatter {
     /// Default constructor
     fn default() -> Self {
-        crate::read_configuration::read_configuration()
+        let formatter = crate::read_configuration::read_configuration();
+        formatter.compile_all().expect("invalid configuration");
+        formatter
     }
 }
 
 impl Formatter {
+    /// Compile all the templates, rules and abbreviations
+    fn compile_all(&self) -> Result<(), Error> {
+        self.templates.compile_all()?;
+        for (lang, abbreviations) in &self.abbreviations {
+            abbreviations
+                .regexes()
+                .map_err(|e| e.context(format!("invalid abbreviations for {}", lang)))?;
+        }
+        Ok(())
+    }
+
+    /// Build a [`Formatter`](struct.Formatter.html) that compiles the templates and rules of a country
+    /// only the first time a [`Place`](struct.Place.html) of this country is formatted.
+    ///
+    /// The construction is way faster than with [`Formatter::default`](struct.Formatter.html#impl-Default),
+    /// but an invalid template or rule is only detected when it is used.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate maplit;
+    /// # fn main() {
+    ///    use address_formatter::Component::*;
+    ///    let formatter = address_formatter::Formatter::lazy();
+    ///
+    ///    assert_eq!(
+    ///        formatter.format(hashmap!(
+    ///            City => "Toulouse",
+    ///            Country => "France",
+    ///            CountryCode => "FR",
+    ///            Postcode => "31000",
+    ///            Road => "Rue du Médecin-Colonel Calbairac",
+    ///        )).unwrap(),
+    ///        r#"Rue du Médecin-Colonel Calbairac
+    ///31000 Toulouse
+    ///France
+    ///"#
+    ///        .to_owned()
+    ///    )
+    /// # }
+    /// ```
+    pub fn lazy() -> Self {
+        crate::read_configuration::read_configuration()
+    }
+
     /// make a human readable text from a [`Place`](struct.Place.html)
     /// ```
     /// # #[macro_use] extern crate maplit;
     /// # fn main() {
     ///    use address_formatter::Component::*;
-    ///    let formatter = address_formatter::Formatter::default();
+    ///    let formatter = address_formatter::Formatter::lazy();
     ///
     ///    let addr: address_formatter::Place = hashmap!(
     ///        City => "Toulouse",
@@ -220,32 +622,266 @@ impl Formatter {
         into_addr: impl Into<Place>,
         conf: Configuration,
     ) -> Result<String, Error> {
-        let mut addr = into_addr.into();
+        self.format_with_report(into_addr, conf)
+            .map(|(text, _report)| text)
+    }
+
+    /// make a human readable text from a [`Place`](struct.Place.html),
+    /// and a [`FormatReport`](struct.FormatReport.html) of what happened to its data.
+    /// ```
+    /// # #[macro_use] extern crate maplit;
+    /// # fn main() {
+    ///    use address_formatter::Component::*;
+    ///    let formatter = address_formatter::Formatter::lazy();
+    ///
+    ///    let (text, report) = formatter.format_with_report(hashmap!(
+    ///        City => "Toulouse",
+    ///        Country => "France",
+    ///        CountryCode => "FR",
+    ///        Postcode => "31000;31100",
+    ///        Road => "Rue du Médecin-Colonel Calbairac",
+    ///        Continent => "Europe",
+    ///    ), Default::default()).unwrap();
+    ///
+    ///    assert_eq!(text, "Rue du Médecin-Colonel Calbairac\nToulouse\nFrance\n");
+    ///    assert_eq!(report.cleaned[0].component, Postcode);
+    ///    assert_eq!(report.unused_components, vec![Continent]);
+    ///    assert!(report.has_data_loss());
+    ///    assert!(!report.used_fallback_template());
+    /// # }
+    /// ```
+    pub fn format_with_report(
+        &self,
+        into_addr: impl Into<Place>,
+        conf: Configuration,
+    ) -> Result<(String, FormatReport), Error> {
+        let (mut text, report, addr, template) = self.format_place(into_addr.into(), &conf)?;
+        if conf.postal_label.unwrap_or(false) {
+            let lines = self.to_lines(&text, &report, &addr, template, &conf);
+            text = format!("{}\n", lines.iter().map(|l| l.text.as_str()).join("\n"));
+        }
+        Ok((text, report))
+    }
+
+    /// make the lines of a human readable text from a [`Place`](struct.Place.html),
+    /// with the components found in each line and its role.
+    /// ```
+    /// # #[macro_use] extern crate maplit;
+    /// # fn main() {
+    ///    use address_formatter::{Component::*, Configuration, LineRole};
+    ///    let formatter = address_formatter::Formatter::lazy();
+    ///
+    ///    let lines = formatter.format_lines(hashmap!(
+    ///        City => "Besançon",
+    ///        Country => "France",
+    ///        CountryCode => "FR",
+    ///        Postcode => "25000",
+    ///        Road => "Rue de la Préfecture",
+    ///    ), Configuration {
+    ///        postal_label: Some(true),
+    ///        ..Default::default()
+    ///    }).unwrap();
+    ///
+    ///    assert_eq!(lines[0].text, "Rue de la Préfecture");
+    ///    assert_eq!(lines[0].role, LineRole::Street);
+    ///    assert_eq!(lines[1].text, "25000 BESANCON");
+    ///    assert_eq!(lines[1].components, vec![City, Postcode]);
+    ///    assert_eq!(lines[1].role, LineRole::Locality);
+    ///    assert_eq!(lines[2].text, "FRANCE");
+    /// # }
+    /// ```
+    pub fn format_lines(
+        &self,
+        into_addr: impl Into<Place>,
+        conf: Configuration,
+    ) -> Result<Vec<FormattedLine>, Error> {
+        let (text, report, addr, template) = self.format_place(into_addr.into(), &conf)?;
+        Ok(self.to_lines(&text, &report, &addr, template, &conf))
+    }
+
+    fn to_lines(
+        &self,
+        text: &str,
+        report: &FormatReport,
+        addr: &Place,
+        template: &Template,
+        conf: &Configuration,
+    ) -> Vec<FormattedLine> {
+        let country_code = report
+            .country_code
+            .as_ref()
+            .and_then(|c| CountryCode::from_str(c).ok());
+        let mut lines = split_lines(
+            text,
+            addr,
+            template.line_components(),
+            self.country_direction(&country_code),
+        );
+        if conf.postal_label.unwrap_or(false) {
+            // the rules of the country, or of the country whose template is used (like FR for GF)
+            let country_codes = report
+                .country_code
+                .iter()
+                .chain(addr[Component::CountryCode].iter())
+                .map(|c| c.as_str())
+                .collect::<Vec<_>>();
+            apply_postal_label_rules(&mut lines, &country_codes);
+        }
+        lines
+    }
+
+    /// format the place, and return the text, the report, the place as it has been rendered and its template
+    fn format_place(
+        &self,
+        mut addr: Place,
+        conf: &Configuration,
+    ) -> Result<(String, FormatReport, Place, &Template), Error> {
+        let mut cleaned = if conf.normalize_input.unwrap_or(true) {
+            CleaningPipeline::empty()
+                .with(UnicodeNormalization)
+                .apply(&mut addr)
+        } else {
+            vec![]
+        };
         let country_code = self.find_country_code(&mut addr, conf);
+        if let Some(country_override) = country_code
+            .as_ref()
+            .and_then(|c| self.templates.overrides_by_country.get(c))
+        {
+            country_override.apply(&mut addr);
+        }
+
+        if let Some(policy) = &conf.multi_value_policy {
+            cleaned.extend(
+                CleaningPipeline::empty()
+                    .with(MultiValues(policy.clone()))
+                    .apply(&mut addr),
+            );
+        }
+        cleaned.extend(match &conf.cleaning_pipeline {
+            Some(pipeline) => pipeline.apply(&mut addr),
+            None => DEFAULT_CLEANING_PIPELINE.apply(&mut addr),
+        });
+        let mut invalid_postcode = if conf.normalize_postcodes.unwrap_or(false) {
+            normalize_place_postcode(
+                &mut addr,
+                &country_code,
+                conf.drop_invalid_postcodes.unwrap_or(false),
+                &mut cleaned,
+            )
+        } else {
+            None
+        };
+        // the postcodes of the countries without postcode system are always reported
+        if let (Some(country_code), Some(postcode)) = (&country_code, &addr[Component::Postcode]) {
+            if !uses_postcodes(country_code.as_str()) {
+                invalid_postcode = Some(postcode.clone());
+                if conf.drop_postcodes_without_postal_system.unwrap_or(false) {
+                    cleaned.push(CleaningChange {
+                        step: "no_postcode_system".to_owned(),
+                        component: Component::Postcode,
+                        old_value: addr[Component::Postcode].take(),
+                        new_value: None,
+                    });
+                }
+            }
+        }
 
-        sanity_clean_place(&mut addr);
+        let transliterate = transliteration_enabled(conf);
+        match &conf.output_language {
+            Some(language) => localize_country(&mut addr, &country_code, language),
+            None if transliterate => localize_country(&mut addr, &country_code, "en"),
+            None => {}
+        }
+        let international_country = conf.origin_country.as_ref().and_then(|origin| {
+            self.apply_origin_country(
+                &mut addr,
+                &country_code,
+                origin,
+                conf.output_language.as_deref(),
+            )
+        });
 
-        let template = self.find_template(&addr, &country_code);
+        let template_kind = self.find_template_kind(&addr, &country_code, conf);
+        let template_script = if transliterate {
+            TemplateScript::Latin
+        } else {
+            self.find_template_script(&addr, &country_code, conf)
+        };
+        let template = match template_script {
+            TemplateScript::Latin => self.find_latin_template(template_kind, &country_code),
+            TemplateScript::Native => self.find_template(template_kind, &country_code),
+        };
         let rules = country_code
             .as_ref()
             .and_then(|c| self.templates.rules_by_country.get(c))
-            .unwrap_or_else(|| &self.templates.fallback_rules);
+            .map(|r| r.as_ref())
+            .unwrap_or_else(|| &self.templates.fallback_rules)
+            .rules()?;
 
-        self.preformat(&rules, &mut addr);
+        let rewritten = self.preformat(rules, &mut addr, conf)?;
+        // the states are translated after the preformat, since the state codes are found with the local names
+        if let Some(language) = &conf.output_language {
+            match &conf.region_names {
+                Some(region_names) => region_names.translate_state(&mut addr, language),
+                None => RegionNames::default().translate_state(&mut addr, language),
+            }
+        }
 
         let text = template
-            .handlebar_handler
+            .handlebar_handler()?
             .render(TEMPLATE_NAME, &addr)
             .map_err(|e| e.context("impossible to render template"))?;
 
-        let text = cleanup_rendered(&text, &rules);
+        let mut collapsed_duplicates = vec![];
+        let mut text = cleanup_rendered(&text, &addr, rules, &mut collapsed_duplicates);
+        if let Some(country) = &international_country {
+            text = move_country_to_last_line(&text, country);
+        }
+
+        let report = FormatReport {
+            country_code: country_code.as_ref().map(|c| c.to_string()),
+            template_kind,
+            template_script,
+            cleaned,
+            invalid_postcode,
+            rewritten,
+            unused_components: unused_components(&text, &addr, template),
+            collapsed_duplicates,
+            #[cfg(feature = "transliteration")]
+            untransliterated: false,
+        };
 
-        Ok(text)
+        // the values are isolated once the text is cleaned, the postformat rules apply on the unmarked values
+        if conf.isolate_bidi.unwrap_or(false) {
+            text = isolate_components(&text, &addr, self.country_direction(&country_code));
+        }
+
+        #[cfg(feature = "transliteration")]
+        let report = {
+            let mut report = report;
+            if transliterate {
+                use crate::transliteration::{is_han, transliterate_with};
+                // the reading of the kanji is not known, they are kept
+                let keep_han = country_code.as_ref().map(|c| c.as_str()) == Some("JP");
+                text = transliterate_with(&text, keep_han);
+                for c in Component::iter() {
+                    if let Some(value) = &addr[c] {
+                        addr[c] = Some(transliterate_with(value, keep_han));
+                    }
+                }
+                report.untransliterated = keep_han && text.chars().any(is_han);
+            }
+            report
+        };
+
+        Ok((text, report, addr, template))
     }
 
-    fn find_country_code(&self, addr: &mut Place, conf: Configuration) -> Option<CountryCode> {
+    fn find_country_code(&self, addr: &mut Place, conf: &Configuration) -> Option<CountryCode> {
         let mut country_code = conf
             .country_code
+            .clone()
             .or_else(|| addr[Component::CountryCode].clone())
             .and_then(|s| {
                 CountryCode::from_str(&s)
@@ -256,62 +892,157 @@ impl Formatter {
         // we hardcode some country code values
         if country_code == CountryCode::from_str("NL").ok() {
             if let Some(state) = addr[Component::State].clone() {
+                let lowercase_state = state.to_lowercase();
+
                 if state.as_str() == "Curaçao" {
                     country_code = CountryCode::from_str("CW").ok();
                     addr[Component::Country] = Some("Curaçao".to_owned());
-                }
-                let state = state.to_lowercase();
-
-                if state.as_str() == "sint maarten" {
+                } else if lowercase_state.starts_with("sint maarten") {
                     country_code = CountryCode::from_str("SX").ok();
                     addr[Component::Country] = Some("Sint Maarten".to_owned());
-                } else if state.as_str() == "aruba" {
+                } else if lowercase_state.starts_with("aruba") {
                     country_code = CountryCode::from_str("AW").ok();
                     addr[Component::Country] = Some("Aruba".to_owned());
                 }
             }
         }
 
+        // the place's country code is updated, so the rules use the same country as the template
+        // (the replace rules, the state codes and the abbreviations are found with the place's country code)
+        if let Some(c) = &country_code {
+            addr[Component::CountryCode] = Some(c.to_string());
+        }
+
         country_code
     }
 
-    fn find_template<'a>(
-        &'a self,
+    /// The components needed by a [`Place`](struct.Place.html) of a country
+    /// to be formatted with the main template of the country
+    pub fn minimum_components(&self, country_code: &str) -> &MinimumComponents {
+        CountryCode::from_str(country_code)
+            .ok()
+            .and_then(|c| self.templates.minimum_components_by_country.get(&c))
+            .unwrap_or(&self.templates.default_minimum_components)
+    }
+
+    fn find_template_kind(
+        &self,
+        addr: &Place,
+        country_code: &Option<CountryCode>,
+        conf: &Configuration,
+    ) -> TemplateKind {
+        conf.template_kind.unwrap_or_else(|| {
+            let minimum_components = conf.minimum_components.as_ref().unwrap_or_else(|| {
+                country_code
+                    .as_ref()
+                    .and_then(|c| self.templates.minimum_components_by_country.get(c))
+                    .unwrap_or(&self.templates.default_minimum_components)
+            });
+            if minimum_components.is_satisfied_by(addr) {
+                TemplateKind::Main
+            } else {
+                TemplateKind::Fallback
+            }
+        })
+    }
+
+    fn find_template(
+        &self,
+        template_kind: TemplateKind,
+        country_code: &Option<CountryCode>,
+    ) -> &Template {
+        match template_kind {
+            TemplateKind::Main => country_code
+                .as_ref()
+                .and_then(|c| self.templates.templates_by_country.get(c))
+                .map(|t| t.as_ref())
+                .unwrap_or(&self.templates.default_template),
+            // if the place does not have the minimum fields, we get its country fallback template
+            // if there is a specific one, else we get the default fallback template
+            TemplateKind::Fallback => country_code
+                .as_ref()
+                .and_then(|c| self.templates.fallback_templates_by_country.get(c))
+                .map(|t| t.as_ref())
+                .unwrap_or(&self.templates.fallback_template),
+        }
+    }
+
+    fn find_template_script(
+        &self,
         addr: &Place,
         country_code: &Option<CountryCode>,
-    ) -> &'a Template {
+        conf: &Configuration,
+    ) -> TemplateScript {
+        conf.template_script.unwrap_or_else(|| {
+            let has_latin_template = country_code
+                .as_ref()
+                .filter(|c| self.templates.latin_templates_by_country.contains_key(c))
+                .is_some();
+            if has_latin_template && is_written_in_latin(addr) {
+                TemplateScript::Latin
+            } else {
+                TemplateScript::Native
+            }
+        })
+    }
+
+    /// the direction of the main language of the country
+    fn country_direction(&self, country_code: &Option<CountryCode>) -> TextDirection {
         country_code
             .as_ref()
-            .and_then(|c| {
-                if !has_minimum_place_components(addr) {
-                    // if the place does not have the minimum fields, we get its country fallback template
-                    // if there is a specific one, else we get the default fallback template
-                    self.templates
-                        .fallback_templates_by_country
-                        .get(&c)
-                        .or_else(|| Some(&self.templates.fallback_template))
-                } else {
-                    self.templates.templates_by_country.get(&c)
-                }
-            })
-            .unwrap_or(&self.templates.default_template)
+            .and_then(|c| self.country_to_lang.get(c))
+            .and_then(|langs| langs.first())
+            .map(|l| TextDirection::of_language(l))
+            .unwrap_or(TextDirection::LeftToRight)
     }
 
-    fn preformat(&self, rules: &Rules, addr: &mut Place) {
-        for r in &rules.replace {
-            r.replace_fields(addr);
+    /// the template for the places written in the latin script, if the country has a specific one
+    fn find_latin_template(
+        &self,
+        template_kind: TemplateKind,
+        country_code: &Option<CountryCode>,
+    ) -> &Template {
+        match template_kind {
+            TemplateKind::Main => country_code
+                .as_ref()
+                .and_then(|c| self.templates.latin_templates_by_country.get(c))
+                .map(|t| t.as_ref())
+                .unwrap_or_else(|| self.find_template(template_kind, country_code)),
+            TemplateKind::Fallback => self.find_template(template_kind, country_code),
         }
+    }
 
-        // in some cases, we need to add some components
-        if let Some(add_component) = &rules.add_component {
-            addr[add_component.component] = Some(add_component.new_value.clone());
-        }
-        if let Some(change_country) = &rules.change_country {
-            addr[Component::Country] = Some(change_country.clone());
+    /// prepare the place for the rendering, and return the values rewritten by the replace rules
+    fn preformat(
+        &self,
+        rules: &Rules,
+        addr: &mut Place,
+        conf: &Configuration,
+    ) -> Result<Vec<ComponentRewrite>, Error> {
+        fix_country(addr);
+
+        // the title casing is done first, so the replace rules match the usual case
+        if conf.title_case.unwrap_or(false) {
+            let languages = self.languages(addr);
+            title_case_place(addr, languages);
         }
-        if let Some(change_country_code) = &rules.change_country_code {
-            addr[Component::CountryCode] = Some(change_country_code.clone());
+
+        let before_replace = addr.clone();
+        for r in &rules.replace {
+            r.replace_fields(addr);
         }
+        let rewritten = Component::iter()
+            .filter_map(|c| match (&before_replace[c], &addr[c]) {
+                (Some(old_value), Some(new_value)) if old_value != new_value => {
+                    Some(ComponentRewrite {
+                        component: c,
+                        old_value: old_value.clone(),
+                        new_value: new_value.clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
 
         // we also try to find the state_code/county_code
         if let Some(country) = addr[Component::CountryCode]
@@ -321,11 +1052,7 @@ impl Formatter {
             if addr[Component::StateCode].is_none() {
                 // we try to see if we can use the state_code and the reference table 'state_codes.yaml' to find the state
                 if let Some(state) = &addr[Component::State] {
-                    if let Some(new_state) = self
-                        .state_codes
-                        .get(&(country.clone(), state.to_string()))
-                        .cloned()
-                    {
+                    if let Some(new_state) = self.find_state_code(&country, state) {
                         addr[Component::StateCode] = Some(new_state);
                     }
                 }
@@ -336,14 +1063,89 @@ impl Formatter {
                 if let Some(county) = &addr[Component::County] {
                     if let Some(new_county) = self
                         .county_codes
-                        .get(&(country, county.to_string()))
+                        .get(&(country, county.to_uppercase()))
                         .cloned()
                     {
-                        addr[Component::County] = Some(new_county);
+                        addr[Component::CountyCode] = Some(new_county);
                     }
                 }
             }
         }
+
+        if conf.abbreviate.unwrap_or(false) {
+            self.abbreviate(addr)?;
+        }
+        Ok(rewritten)
+    }
+
+    /// remove the country of a domestic place, or use its international name
+    /// return the international name of the country
+    fn apply_origin_country(
+        &self,
+        addr: &mut Place,
+        country_code: &Option<CountryCode>,
+        origin: &str,
+        output_language: Option<&str>,
+    ) -> Option<String> {
+        let origin = CountryCode::from_str(origin)
+            .map_err(|e| log::info!("invalid origin country: {}", e))
+            .ok()?;
+        // the place's country is compared before the overrides
+        // (a place in GF uses the template of FR, but it is not domestic from FR)
+        if country_code.as_ref() == Some(&origin) {
+            addr[Component::Country] = None;
+            return None;
+        }
+        let language = output_language.unwrap_or_else(|| {
+            self.country_to_lang
+                .get(&origin)
+                .and_then(|langs| langs.iter().find(|l| *l == "en" || *l == "fr"))
+                .map(|l| l.as_str())
+                .unwrap_or("en")
+        });
+        let name = country_code
+            .as_ref()
+            .and_then(|c| country_name(c.as_str(), language))
+            .map(|n| n.to_uppercase())
+            .or_else(|| addr[Component::Country].as_ref().map(|c| c.to_uppercase()))?;
+        addr[Component::Country] = Some(name.clone());
+        Some(name)
+    }
+
+    /// abbreviate the components with the abbreviations of all the languages of the country
+    fn abbreviate(&self, addr: &mut Place) -> Result<(), Error> {
+        for lang in self.languages(addr) {
+            if let Some(abbreviations) = self.abbreviations.get(lang) {
+                abbreviations.apply(addr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// the languages of the place's country
+    fn languages(&self, addr: &Place) -> &[String] {
+        addr[Component::CountryCode]
+            .as_ref()
+            .and_then(|c| CountryCode::from_str(c).ok())
+            .and_then(|c| self.country_to_lang.get(&c))
+            .map(|langs| langs.as_slice())
+            .unwrap_or(&[])
+    }
+
+    fn find_state_code(&self, country: &CountryCode, state: &str) -> Option<String> {
+        let state = state.to_uppercase();
+        self.state_codes
+            .get(&(country.clone(), state.clone()))
+            .or_else(|| {
+                // try again for odd variants like "United States Virgin Islands"
+                if country.as_str() == "US" && state.starts_with("UNITED STATES") {
+                    let state = state.replacen("UNITED STATES", "US", 1);
+                    self.state_codes.get(&(country.clone(), state))
+                } else {
+                    None
+                }
+            })
+            .cloned()
     }
 }
 
@@ -364,9 +1166,10 @@ impl PlaceBuilder {
     /// Build a [`Place`](struct.Place.html)(crate::Place) from an unstructed source (like Nominatim output)
     pub fn build_place<'a>(&self, values: impl IntoIterator<Item = (&'a str, String)>) -> Place {
         let mut place = Place::default();
-        let mut unknown = HashMap::<String, String>::new();
+        // the unknown fields are sorted to have a deterministic 'Attention' field
+        let mut unknown = BTreeMap::<String, String>::new();
         for (k, v) in values.into_iter() {
-            let component = Component::from_str(k).ok();;
+            let component = Component::from_str(k).ok();
             if let Some(component) = component {
                 place[component] = Some(v);
             } else {
@@ -389,55 +1192,195 @@ impl PlaceBuilder {
             place[Component::Attention] = Some(unknown.values().join(", "));
         }
 
-        // hardocded cleanup for some bad country data
-        if let (Some(state), Some(country)) = (&place[Component::State], &place[Component::Country])
-        {
-            if country.parse::<usize>().is_ok() {
-                place[Component::Country] = Some(state.clone());
-                place[Component::State] = None;
-            }
-        }
         place
     }
 }
 
-fn sanity_clean_place(addr: &mut Place) {
-    lazy_static::lazy_static! {
-        static ref POST_CODE_RANGE: Regex = Regex::new(r#"\d+;\d+"#).unwrap();
-        static ref MATCHABLE_POST_CODE_RANGE: Regex = Regex::new(r#"^(\d{5}),\d{5}"#).unwrap();
-        static ref IS_URL: Regex= Regex::new(r#"https?://"#).unwrap();
-
-    }
-    // cleanup the postcode
-    if let Some(post_code) = &addr[Component::Postcode] {
-        if post_code.len() > 20 || POST_CODE_RANGE.is_match(post_code) {
-            addr[Component::Postcode] = None;
-        } else if let Some(r) = MATCHABLE_POST_CODE_RANGE
-            .captures(post_code)
-            .and_then(|r| r.get(1))
-            .map(|c| c.as_str())
-        {
-            addr[Component::Postcode] = Some(r.to_owned());
+#[cfg(feature = "transliteration")]
+fn transliteration_enabled(conf: &Configuration) -> bool {
+    conf.transliterate.unwrap_or(false)
+}
+
+#[cfg(not(feature = "transliteration"))]
+fn transliteration_enabled(_conf: &Configuration) -> bool {
+    false
+}
+
+/// true if all the letters of the main components are latin letters
+fn is_written_in_latin(addr: &Place) -> bool {
+    let mut letters = SCRIPT_COMPONENTS
+        .iter()
+        .filter_map(|c| addr[*c].as_ref())
+        .flat_map(|v| v.chars())
+        .map(|c| c.script())
+        .filter(|s| *s != Script::Common && *s != Script::Inherited)
+        .peekable();
+    letters.peek().is_some() && letters.all(|s| s == Script::Latin)
+}
+
+/// the country must be on the last line for the international mails
+fn move_country_to_last_line(text: &str, country: &str) -> String {
+    let lines = text
+        .lines()
+        .filter(|l| *l != country)
+        .chain(std::iter::once(country))
+        .join("\n");
+    format!("{}\n", lines)
+}
+
+/// normalize the postcode of the place, and return it if it is not valid
+/// the changes are added to the cleaning changes
+fn normalize_place_postcode(
+    addr: &mut Place,
+    country_code: &Option<CountryCode>,
+    drop_invalid: bool,
+    changes: &mut Vec<CleaningChange>,
+) -> Option<String> {
+    let (country_code, postcode) = match (country_code, &addr[Component::Postcode]) {
+        (Some(c), Some(p)) => (c, p.clone()),
+        _ => return None,
+    };
+    let (new_value, invalid_postcode) = match normalize_postcode(country_code.as_str(), &postcode) {
+        Ok(normalized) => (Some(normalized), None),
+        Err(e) => {
+            log::info!("{}", e);
+            let new_value = if drop_invalid {
+                None
+            } else {
+                Some(postcode.clone())
+            };
+            (new_value, Some(postcode.clone()))
+        }
+    };
+    if new_value.as_ref() != Some(&postcode) {
+        changes.push(CleaningChange {
+            step: "normalize_postcode".to_owned(),
+            component: Component::Postcode,
+            old_value: Some(postcode),
+            new_value: new_value.clone(),
+        });
+        addr[Component::Postcode] = new_value;
+    }
+    invalid_postcode
+}
+
+/// if the country is a number, we use the state as country
+fn fix_country(addr: &mut Place) {
+    if let (Some(state), Some(country)) = (&addr[Component::State], &addr[Component::Country]) {
+        if country.trim().parse::<f64>().is_ok() {
+            addr[Component::Country] = Some(state.clone());
+            addr[Component::State] = None;
         }
     }
+}
+
+lazy_static::lazy_static! {
+    static ref DEFAULT_CLEANING_PIPELINE: CleaningPipeline = CleaningPipeline::default();
+    static ref HAS_WORD: Regex = Regex::new(r"\w").unwrap();
+}
 
-    // clean values containing URLs
-    for c in Component::iter() {
-        if let Some(v) = &addr[c] {
-            if IS_URL.is_match(v) {
-                addr[c] = None;
+/// apply a replacement on a string
+/// to improve performance, we update the string only if it was changed by the replace
+fn replace_in_place(res: &mut String, replace: impl FnOnce(&str) -> std::borrow::Cow<str>) {
+    if let std::borrow::Cow::Owned(v) = replace(res) {
+        *res = v;
+    }
+}
+
+fn cleanup_rendered(
+    text: &str,
+    addr: &Place,
+    rules: &Rules,
+    collapsed_duplicates: &mut Vec<String>,
+) -> String {
+    let mut res = clean(text, collapsed_duplicates);
+
+    // if the template gave nothing, but there is only one component, we use it
+    if !HAS_WORD.is_match(&res) {
+        if let Ok(value) = addr.values().flatten().exactly_one() {
+            res = value.clone();
+        }
+    }
+
+    let res = postformat(&res, addr, rules, collapsed_duplicates);
+
+    clean(&res, collapsed_duplicates)
+}
+
+/// the components of the place not rendered by the template (unless they duplicate a rendered value),
+/// or whose value has been removed by the cleanup of the rendered text
+/// (the codes are not considered, they are only used by some templates)
+fn unused_components(text: &str, addr: &Place, template: &Template) -> Vec<Component> {
+    let used_components = template.used_components(addr);
+    let used_values = used_components
+        .iter()
+        .filter_map(|c| addr[*c].as_ref())
+        .collect::<HashSet<_>>();
+    Component::iter()
+        .filter(|c| {
+            !matches!(
+                c,
+                Component::CountryCode | Component::StateCode | Component::CountyCode
+            )
+        })
+        .filter(|c| match &addr[*c] {
+            Some(v) if used_components.contains(c) => !text.contains(v.as_str()),
+            Some(v) => !used_values.contains(v),
+            None => false,
+        })
+        .collect()
+}
+
+/// apply the postformat rules of the country on the formatted text
+fn postformat(
+    text: &str,
+    addr: &Place,
+    rules: &Rules,
+    collapsed_duplicates: &mut Vec<String>,
+) -> String {
+    // remove duplicates
+    let mut seen = HashSet::new();
+    let mut res = text
+        .split(", ")
+        .map(|piece| piece.trim_start())
+        .filter(|piece| {
+            let keep = piece.to_lowercase() == "new york" || seen.insert(*piece);
+            if !keep {
+                collapsed_duplicates.push(piece.to_string());
+            }
+            keep
+        })
+        .join(", ");
+
+    for r in &rules.postformat_replace {
+        match r {
+            ReplaceRule::All(r) => {
+                replace_in_place(&mut res, |s| r.replace(s));
+            }
+            ReplaceRule::Component((c, _, r)) => {
+                // the rule is applied only on the part of the text coming from the component
+                if let Some(value) = &addr[*c] {
+                    let new_value = r.replace(value);
+                    if new_value != value.as_str() {
+                        res = res.replacen(value.as_str(), &new_value, 1);
+                    }
+                }
             }
         }
     }
+    res
 }
 
-fn cleanup_rendered(text: &str, rules: &Rules) -> String {
-    use itertools::Itertools;
+/// cleanup a formatted text, remove all the useless whitespaces, commas and duplicates
+fn clean(text: &str, collapsed_duplicates: &mut Vec<String>) -> String {
     lazy_static::lazy_static! {
-        static ref REPLACEMENTS:  [(Regex, &'static str); 12]= [
+        // those replacements are applied only on the first match
+        static ref FIRST_REPLACEMENTS:  [(Regex, &'static str); 3]= [
             (RegexBuilder::new(r"[},\s]+$").multi_line(true).build().unwrap(), ""),
-            (RegexBuilder::new(r"^ - ").multi_line(true).build().unwrap(), ""), // line starting with dash due to a parameter missing
             (RegexBuilder::new(r"^[,\s]+").multi_line(true).build().unwrap(), ""),
+            (RegexBuilder::new(r"^- ").multi_line(true).build().unwrap(), ""), // line starting with dash due to a parameter missing
+        ];
+        static ref REPLACEMENTS:  [(Regex, &'static str); 9]= [
             (RegexBuilder::new(r",\s*,").multi_line(true).build().unwrap(), ", "), //multiple commas to one
             (RegexBuilder::new(r"[\t\p{Zs}]+,[\t\p{Zs}]+").multi_line(true).build().unwrap(), ", "), //one horiz whitespace behind comma
             (RegexBuilder::new(r"[\t ][\t ]+").multi_line(true).build().unwrap(), " "), //multiple horiz whitespace to one
@@ -457,81 +1400,135 @@ fn cleanup_rendered(text: &str, rules: &Rules) -> String {
 
     let mut res = text.to_owned();
 
+    for (rgx, new_val) in FIRST_REPLACEMENTS.iter() {
+        replace_in_place(&mut res, |s| rgx.replace(s, *new_val));
+    }
     for (rgx, new_val) in REPLACEMENTS.iter() {
-        let rep = rgx.replace_all(&res, *new_val);
-        // to improve performance, we update the string only if it was changed by the replace
-        match rep {
-            std::borrow::Cow::Borrowed(_) => {}
-            std::borrow::Cow::Owned(v) => {
-                res = v;
-            }
-        }
+        replace_in_place(&mut res, |s| rgx.replace_all(s, *new_val));
     }
 
-    for r in &rules.postformat_replace {
-        let rep = r.regex.replace_all(&res, r.replacement_value.as_str());
-        match rep {
-            std::borrow::Cow::Borrowed(_) => {}
-            std::borrow::Cow::Owned(v) => {
-                res = v;
+    // we also dedup the string
+    // we trim and dedup all the same 'token' (separated by commas) in a line
+    // ('New York' can be repeated since it is both a city and a state)
+    // and all the same lines too
+    let mut seen_lines = HashSet::new();
+    let mut lines = vec![];
+    for line in res.split('\n').map(|s| s.trim()) {
+        if !seen_lines.insert(line) {
+            if !line.is_empty() {
+                collapsed_duplicates.push(line.to_owned());
+            }
+            continue;
+        }
+        let mut seen_tokens = HashSet::new();
+        let mut tokens = vec![];
+        for token in line.split(',').map(|e| e.trim()) {
+            if token.to_lowercase() == "new york" || seen_tokens.insert(token) {
+                tokens.push(token);
+            } else {
+                collapsed_duplicates.push(token.to_owned());
             }
         }
+        lines.push(tokens.join(", "));
     }
-
-    // we also dedup the string
-    // we dedup and trim and all the same 'token' in a line
-    // and all the same lines too
-    let mut res = res
-        .split('\n')
-        .map(|s| s.split(", ").map(|e| e.trim()).dedup().join(", "))
-        .dedup()
-        .join("\n");
+    let mut res = lines.join("\n");
 
     for (rgx, new_val) in FINAL_CLEANUP.iter() {
-        let rep = rgx.replace(&res, *new_val);
-        match rep {
-            std::borrow::Cow::Borrowed(_) => {}
-            std::borrow::Cow::Owned(v) => {
-                res = v;
-            }
-        }
+        replace_in_place(&mut res, |s| rgx.replace(s, *new_val));
     }
 
     let res = res.trim();
     format!("{}\n", res) //add final newline
 }
 
-fn has_minimum_place_components(addr: &Place) -> bool {
-    // if there are neither 'road' nor 'postcode', we consider that there are not enough data
-    // and use the fallback template
-    addr[Component::Road].is_some() || addr[Component::Postcode].is_some()
-}
-
 impl ReplaceRule {
     fn replace_fields(&self, addr: &mut Place) {
         match self {
             ReplaceRule::All(replace_rule) => {
                 for c in Component::iter() {
                     if let Some(v) = &addr[c] {
-                        addr[c] = Some(
-                            replace_rule
-                                .regex
-                                .replace(&v, replace_rule.replacement_value.as_str())
-                                .to_string(),
-                        );
+                        addr[c] = Some(replace_rule.replace(v).to_string());
                     }
                 }
             }
-            ReplaceRule::Component((c, replace_rule)) => {
+            ReplaceRule::Component((c, raw_value, replace_rule)) => {
                 if let Some(v) = &addr[*c] {
-                    addr[*c] = Some(
-                        replace_rule
-                            .regex
-                            .replace(&v, replace_rule.replacement_value.as_str())
-                            .to_string(),
-                    );
+                    // if the component is exactly the value to replace, it is replaced
+                    // even if it contains some regex special characters
+                    addr[*c] = Some(if v == raw_value {
+                        replace_rule.replacement_value.clone()
+                    } else {
+                        replace_rule.replace(v).to_string()
+                    });
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_country_code_is_the_resolved_one() {
+        let formatter = Formatter::lazy();
+
+        // the country of the configuration is used
+        let mut addr = Place::default();
+        addr[Component::CountryCode] = Some("DE".to_owned());
+        let conf = Configuration {
+            country_code: Some("fr".to_owned()),
+            ..Default::default()
+        };
+        let country_code = formatter.find_country_code(&mut addr, &conf);
+        assert_eq!(country_code, CountryCode::from_str("FR").ok());
+        assert_eq!(addr[Component::CountryCode], Some("FR".to_owned()));
+
+        // the hardcoded countries
+        let mut addr = Place::default();
+        addr[Component::CountryCode] = Some("nl".to_owned());
+        addr[Component::State] = Some("Curaçao".to_owned());
+        let country_code = formatter.find_country_code(&mut addr, &Configuration::default());
+        assert_eq!(country_code, CountryCode::from_str("CW").ok());
+        assert_eq!(addr[Component::CountryCode], Some("CW".to_owned()));
+
+        // an invalid country code is kept as is
+        let mut addr = Place::default();
+        addr[Component::CountryCode] = Some("FRA".to_owned());
+        assert_eq!(
+            formatter.find_country_code(&mut addr, &Configuration::default()),
+            None
+        );
+        assert_eq!(addr[Component::CountryCode], Some("FRA".to_owned()));
+    }
+
+    #[test]
+    fn postformat_rules_with_isolated_components() {
+        let mut formatter = Formatter::default();
+        // a postformat rule on the postcode, which is isolated in an arabic place
+        formatter.templates.rules_by_country.insert(
+            CountryCode::from_str("EG").unwrap(),
+            Arc::new(LazyRules::new(
+                vec![],
+                vec![("\n(\\d{5}) ".to_owned(), "\n$1, ".to_owned())],
+            )),
+        );
+        let mut addr = Place::default();
+        addr[Component::City] = Some("القاهرة".to_owned());
+        addr[Component::CountryCode] = Some("EG".to_owned());
+        addr[Component::Postcode] = Some("11511".to_owned());
+        addr[Component::Road] = Some("شارع عمر بن الخطاب".to_owned());
+
+        let text = formatter
+            .format_with_config(
+                addr,
+                Configuration {
+                    isolate_bidi: Some(true),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(text, "شارع عمر بن الخطاب\n\u{2068}11511\u{2069}, القاهرة\n");
+    }
+}
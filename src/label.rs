@@ -0,0 +1,186 @@
+use crate::{Component, Configuration, FormattedLine, Formatter, Place};
+use failure::Error;
+use serde::Serialize;
+
+/// The size constraints of a printed label.
+///
+/// The default values follow the [UPU S42 standard](https://www.upu.int/en/Postal-Solutions/Programmes-Services/Addressing-Solutions):
+/// at most 6 lines of 35 characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LabelSize {
+    /// maximum number of characters in a line
+    pub max_width: usize,
+    /// maximum number of lines
+    pub max_lines: usize,
+}
+
+impl Default for LabelSize {
+    fn default() -> Self {
+        LabelSize {
+            max_width: 35,
+            max_lines: 6,
+        }
+    }
+}
+
+/// A [`Place`](struct.Place.html) rendered to fit in a [`LabelSize`](struct.LabelSize.html)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Label {
+    /// lines of the label
+    pub lines: Vec<String>,
+    /// true if the components have been abbreviated
+    pub abbreviated: bool,
+    /// true if some lines have been wrapped
+    pub wrapped: bool,
+    /// the components removed to reduce the number of lines
+    pub removed_components: Vec<Component>,
+    /// false if the place could not fit in the label,
+    /// the lines are then too long or too many
+    pub fits: bool,
+}
+
+/// The components that can be removed to fit in a label, from the least important one
+/// (following the UPU S42 priorities)
+const REMOVABLE_COMPONENTS: [Component; 10] = [
+    Component::Attention,
+    Component::Neighbourhood,
+    Component::Suburb,
+    Component::CityDistrict,
+    Component::County,
+    Component::StateDistrict,
+    Component::Region,
+    Component::Island,
+    Component::State,
+    Component::House,
+];
+
+impl Formatter {
+    /// Render a [`Place`](struct.Place.html) as a label with a limited number of lines and characters by line.
+    ///
+    /// To fit the label:
+    /// * the components are abbreviated first (like "Avenue" to "Ave"),
+    ///   unless `abbreviate` is set in the [`Configuration`](struct.Configuration.html),
+    /// * then the lines too long are wrapped at the component boundaries (or at the words boundaries),
+    /// * then the least important components are removed (like `Attention`, `Suburb` or `County`).
+    ///
+    /// The returned [`Label`](struct.Label.html) tells if the place could fit.
+    ///
+    /// ```
+    /// # #[macro_use] extern crate maplit;
+    /// # fn main() {
+    ///    use address_formatter::{Component::*, LabelSize};
+    ///    let formatter = address_formatter::Formatter::lazy();
+    ///
+    ///    let label = formatter.format_label(hashmap!(
+    ///        City => "Toulouse",
+    ///        Country => "France",
+    ///        CountryCode => "FR",
+    ///        HouseNumber => "17",
+    ///        Postcode => "31000",
+    ///        Road => "Avenue du Médecin-Colonel Calbairac",
+    ///    ), Default::default(), LabelSize { max_width: 30, max_lines: 6 }).unwrap();
+    ///
+    ///    assert!(label.fits);
+    ///    assert!(label.lines.iter().all(|l| l.chars().count() <= 30));
+    /// # }
+    /// ```
+    pub fn format_label(
+        &self,
+        into_addr: impl Into<Place>,
+        conf: Configuration,
+        size: LabelSize,
+    ) -> Result<Label, Error> {
+        let mut addr = into_addr.into();
+        let mut label = Label {
+            lines: vec![],
+            abbreviated: conf.abbreviate.unwrap_or(false),
+            wrapped: false,
+            removed_components: vec![],
+            fits: false,
+        };
+        let mut conf = conf;
+        let mut removable_components = REMOVABLE_COMPONENTS.iter();
+        loop {
+            let lines = self.format_lines(addr.clone(), conf.clone())?;
+            label.lines = lines.iter().map(|l| l.text.clone()).collect();
+            if fits(&label.lines, size) {
+                label.fits = true;
+                break;
+            }
+            if conf.abbreviate.is_none() {
+                label.abbreviated = true;
+                conf.abbreviate = Some(true);
+                continue;
+            }
+            let wrapped_lines = wrap(&lines, size.max_width);
+            label.wrapped = wrapped_lines.len() > lines.len();
+            label.lines = wrapped_lines;
+            if fits(&label.lines, size) {
+                label.fits = true;
+                break;
+            }
+            match removable_components.find(|c| addr[**c].is_some()) {
+                Some(c) => {
+                    addr[*c] = None;
+                    label.removed_components.push(*c);
+                }
+                None => break,
+            }
+        }
+        if !label.fits {
+            log::info!(
+                "impossible to fit the place in the label: {:?}",
+                label.lines
+            );
+        }
+        Ok(label)
+    }
+}
+
+fn fits(lines: &[String], size: LabelSize) -> bool {
+    lines.len() <= size.max_lines && lines.iter().all(|l| l.chars().count() <= size.max_width)
+}
+
+/// wrap the lines too long, at the component boundaries (the commas) if possible, else at the words boundaries
+/// the words too long are not cut
+fn wrap(lines: &[FormattedLine], max_width: usize) -> Vec<String> {
+    let mut wrapped_lines = vec![];
+    for line in lines {
+        if line.text.chars().count() <= max_width {
+            wrapped_lines.push(line.text.clone());
+            continue;
+        }
+        // the components short enough are grouped, the others are wrapped at the words boundaries
+        let mut short_pieces = vec![];
+        for piece in line.text.split(", ") {
+            if piece.chars().count() <= max_width {
+                short_pieces.push(piece);
+            } else {
+                wrapped_lines.extend(wrap_pieces(&short_pieces, ", ", max_width));
+                short_pieces.clear();
+                let words = piece.split_whitespace().collect::<Vec<_>>();
+                wrapped_lines.extend(wrap_pieces(&words, " ", max_width));
+            }
+        }
+        wrapped_lines.extend(wrap_pieces(&short_pieces, ", ", max_width));
+    }
+    wrapped_lines
+}
+
+/// group the pieces in lines of at most `max_width` characters
+fn wrap_pieces(pieces: &[&str], separator: &str, max_width: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for piece in pieces {
+        match lines.last_mut() {
+            Some(line)
+                if line.chars().count() + separator.chars().count() + piece.chars().count()
+                    <= max_width =>
+            {
+                line.push_str(separator);
+                line.push_str(piece);
+            }
+            _ => lines.push(piece.to_string()),
+        }
+    }
+    lines
+}
@@ -47,7 +47,7 @@
 //! ```
 //! # fn main() {
 //!    // use can either use the provider singleton address_formatter::FORMATTER or build your own
-//!    let formatter = address_formatter::Formatter::default();
+//!    let formatter = address_formatter::Formatter::lazy();
 //!    let addr_builder = address_formatter::PlaceBuilder::default();
 //!    let data = [
 //!        ("building", "Mairie (bureaux administratifs)"),
@@ -78,13 +78,46 @@
 //! # }
 //! ```
 
+pub(crate) mod bidi;
+pub(crate) mod cleaning;
+pub(crate) mod conformance;
+pub(crate) mod country;
 pub(crate) mod formatter;
 pub(crate) mod handlebar_helper;
+pub(crate) mod label;
 pub(crate) mod place;
+pub(crate) mod postal;
+pub(crate) mod postcode;
 pub(crate) mod read_configuration;
+pub(crate) mod region;
+pub(crate) mod replacement;
+pub(crate) mod report;
+pub(crate) mod title_case;
+#[cfg(feature = "transliteration")]
+pub(crate) mod transliteration;
 
-pub use formatter::{Configuration, Formatter, PlaceBuilder};
+pub use bidi::TextDirection;
+pub use cleaning::{
+    CleaningChange, CleaningPipeline, CleaningStep, FuzzyDuplicates, MultiValuePolicy, MultiValues,
+    NoWordCharacter, PostcodeList, PostcodeMaxLength, PostcodeRange, UnicodeNormalization, Url,
+};
+pub use conformance::{
+    read_test_cases, run as run_conformance, run_test_case, ConformanceReport, CountryReport,
+    ExpectedFailures, TestCase, TestCaseResult,
+};
+pub use country::country_name;
+pub use formatter::{
+    Configuration, Formatter, MinimumComponents, PlaceBuilder, TemplateKind, TemplateScript,
+};
+pub use label::{Label, LabelSize};
 pub use place::{Component, Place};
+pub use postal::{FormattedLine, LineRole};
+pub use postcode::{normalize_postcode, uses_postcodes};
+pub use region::RegionNames;
+pub use report::{ComponentRewrite, FormatReport};
+pub use title_case::title_case;
+#[cfg(feature = "transliteration")]
+pub use transliteration::transliterate;
 
 lazy_static::lazy_static! {
     /// Singleton to ease use of the [`Formatter`](struct.Formatter.html)
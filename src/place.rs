@@ -63,7 +63,7 @@ impl serde::Serialize for Component {
 ///
 ///
 /// Note: it is internally represented as an EnumMap to easily loop over all the fields
-#[derive(Debug, Default, Serialize)]
+#[derive(Debug, Default, Clone, Serialize)]
 pub struct Place(EnumMap<Component, Option<String>>);
 
 impl std::ops::Deref for Place {
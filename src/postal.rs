@@ -0,0 +1,206 @@
+use crate::bidi::TextDirection;
+use crate::formatter::CountryCode;
+use crate::{Component, Place};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::str::FromStr;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// The role of a line of a formatted [`Place`](struct.Place.html), deduced from its components
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineRole {
+    /// the name of the place (the `Attention` or the `House`)
+    Recipient,
+    /// the house number and the road
+    Street,
+    /// the postcode and the city (or town, village, ...)
+    Locality,
+    /// the state, county, ...
+    Region,
+    /// the country
+    Country,
+    /// a line without any known component
+    Other,
+}
+
+/// A line of a formatted [`Place`](struct.Place.html)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FormattedLine {
+    /// text of the line
+    pub text: String,
+    /// the components of the place rendered in the line
+    pub components: Vec<Component>,
+    /// role of the line
+    pub role: LineRole,
+    /// direction of the line, from its first letter (or from the language of the country if it has no letter)
+    pub direction: TextDirection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PostalLabelRules {
+    #[serde(default)]
+    uppercase: Vec<LineRole>,
+    #[serde(default)]
+    no_punctuation: Vec<LineRole>,
+    #[serde(default)]
+    no_diacritics: Vec<LineRole>,
+}
+
+#[derive(Deserialize)]
+struct RawPostalLabelRules {
+    countries: HashMap<String, PostalLabelRules>,
+}
+
+lazy_static::lazy_static! {
+    static ref POSTAL_LABEL_RULES: HashMap<CountryCode, PostalLabelRules> = {
+        let rules: RawPostalLabelRules = serde_yaml::from_str(include_str!("../data/postal_labels.yaml"))
+            .expect("invalid postal_labels.yaml file");
+        rules
+            .countries
+            .into_iter()
+            .map(|(country, rules)| (CountryCode::from_str(&country).expect("invalid country code"), rules))
+            .collect()
+    };
+}
+
+/// split the formatted text in lines, and find the components and the role of each line
+///
+/// The components of a line are the ones of the template line rendering it: the template line whose
+/// components cover most of the line (the next one in the template order on a tie),
+/// so the road "Avenue de Paris" is not taken for the city "Paris".
+/// The lines not rendered by a template line get all the components found in them.
+pub(crate) fn split_lines(
+    text: &str,
+    addr: &Place,
+    template_lines: &[Vec<Component>],
+    default_direction: TextDirection,
+) -> Vec<FormattedLine> {
+    let mut next_template_line = 0;
+    text.lines()
+        .map(|line| {
+            let found: Vec<(Component, usize)> = addr
+                .iter()
+                .filter_map(|(c, v)| v.as_ref().map(|v| (c, v)))
+                .filter(|(_, v)| contains_words(line, v))
+                .map(|(c, v)| (c, v.chars().count()))
+                .collect();
+            let coverage = |template_line: &Vec<Component>| -> usize {
+                found
+                    .iter()
+                    .filter(|(c, _)| template_line.contains(c))
+                    .map(|(_, len)| len)
+                    .sum()
+            };
+            let template_line = template_lines
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| coverage(l) > 0)
+                .max_by_key(|(i, l)| (coverage(l), *i >= next_template_line, Reverse(*i)));
+            let components: Vec<Component> = match template_line {
+                Some((i, template_line)) => {
+                    next_template_line = i + 1;
+                    found
+                        .into_iter()
+                        .map(|(c, _)| c)
+                        .filter(|c| template_line.contains(c))
+                        .collect()
+                }
+                None => found.into_iter().map(|(c, _)| c).collect(),
+            };
+            let role = line_role(&components);
+            FormattedLine {
+                text: line.to_owned(),
+                components,
+                role,
+                direction: TextDirection::of_text(line).unwrap_or(default_direction),
+            }
+        })
+        .collect()
+}
+
+fn line_role(components: &[Component]) -> LineRole {
+    use Component::*;
+    let has_any = |cs: &[Component]| cs.iter().any(|c| components.contains(c));
+    if has_any(&[Country]) {
+        LineRole::Country
+    } else if has_any(&[Postcode, City, Town, Village]) {
+        LineRole::Locality
+    } else if has_any(&[Road, HouseNumber]) {
+        LineRole::Street
+    } else if has_any(&[Attention, House]) {
+        LineRole::Recipient
+    } else if has_any(&[
+        Suburb,
+        CityDistrict,
+        Neighbourhood,
+        County,
+        StateDistrict,
+        State,
+        StateCode,
+        Region,
+        Island,
+    ]) {
+        LineRole::Region
+    } else {
+        LineRole::Other
+    }
+}
+
+/// true if the value is in the line, and not only as a part of a word
+/// (so the house number '1' is not found in the postcode '31000')
+fn contains_words(line: &str, value: &str) -> bool {
+    let is_boundary = |c: Option<char>| c.filter(|c| c.is_alphanumeric()).is_none();
+    line.match_indices(value).any(|(i, _)| {
+        is_boundary(line[..i].chars().next_back())
+            && is_boundary(line[i + value.len()..].chars().next())
+    })
+}
+
+/// apply the postal rules of the first country having some on the lines
+pub(crate) fn apply_postal_label_rules(lines: &mut [FormattedLine], country_codes: &[&str]) {
+    let rules = match country_codes
+        .iter()
+        .filter_map(|c| CountryCode::from_str(c).ok())
+        .find_map(|c| POSTAL_LABEL_RULES.get(&c))
+    {
+        Some(r) => r,
+        None => return,
+    };
+    for line in lines {
+        if rules.no_punctuation.contains(&line.role) {
+            line.text = remove_punctuation(&line.text);
+        }
+        if rules.no_diacritics.contains(&line.role) {
+            line.text = remove_diacritics(&line.text);
+        }
+        if rules.uppercase.contains(&line.role) {
+            line.text = line.text.to_uppercase();
+        }
+    }
+}
+
+/// remove the punctuation, except the characters used in the house numbers and names like '-', '/', '#' or '&'
+fn remove_punctuation(text: &str) -> String {
+    text.chars()
+        .filter_map(|c| match c {
+            '-' | '/' | '#' | '&' => Some(c),
+            // "St. Louis" gives "St Louis" and "O'Brien" gives "OBrien"
+            '.' | '\'' | '’' => None,
+            c if c.is_ascii_punctuation() || "«»…".contains(c) => Some(' '),
+            c => Some(c),
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn remove_diacritics(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .nfc()
+        .collect()
+}
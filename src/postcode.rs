@@ -0,0 +1,107 @@
+use crate::formatter::CountryCode;
+use failure::{format_err, Error};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+lazy_static::lazy_static! {
+    static ref RAW_POSTCODE_FORMATS: RawPostcodeFormats =
+        serde_yaml::from_str(include_str!("../data/postcodes.yaml")).expect("invalid postcodes.yaml file");
+    static ref POSTCODE_FORMATS: HashMap<CountryCode, Vec<(Regex, String)>> = read_postcode_formats();
+    pub(crate) static ref COUNTRIES_WITHOUT_POSTCODES: HashSet<CountryCode> = RAW_POSTCODE_FORMATS
+        .without_postcodes
+        .iter()
+        .map(|c| CountryCode::from_str(c).expect("invalid country code"))
+        .collect();
+}
+
+#[derive(serde::Deserialize)]
+struct RawPostcodeFormats {
+    countries: HashMap<String, Vec<(String, String)>>,
+    without_postcodes: Vec<String>,
+}
+
+fn read_postcode_formats() -> HashMap<CountryCode, Vec<(Regex, String)>> {
+    RAW_POSTCODE_FORMATS
+        .countries
+        .iter()
+        .map(|(country, formats)| {
+            (
+                CountryCode::from_str(country).expect("invalid country code"),
+                formats
+                    .iter()
+                    .map(|(regex, format)| {
+                        (
+                            Regex::new(regex).expect("invalid postcode regex"),
+                            format.clone(),
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
+
+/// Normalize the postcode of a country to its official format, and check it is valid.
+///
+/// The postcodes of the countries whose format is not known are only trimmed,
+/// and all the postcodes of the countries without postcode system are invalid.
+///
+/// ```
+/// use address_formatter::normalize_postcode;
+///
+/// assert_eq!(normalize_postcode("GB", "sw1a1aa").unwrap(), "SW1A 1AA");
+/// assert_eq!(normalize_postcode("NL", "1234ab").unwrap(), "1234 AB");
+/// assert_eq!(normalize_postcode("CA", "k1a0b1").unwrap(), "K1A 0B1");
+/// assert_eq!(normalize_postcode("BR", "01310100").unwrap(), "01310-100");
+/// assert!(normalize_postcode("FR", "3100").is_err());
+/// assert!(normalize_postcode("AE", "00000").is_err());
+/// ```
+pub fn normalize_postcode(country_code: &str, postcode: &str) -> Result<String, Error> {
+    let country_code = CountryCode::from_str(country_code)?;
+    if COUNTRIES_WITHOUT_POSTCODES.contains(&country_code) {
+        return Err(format_err!("{} has no postcode system", country_code));
+    }
+    let formats = match POSTCODE_FORMATS.get(&country_code) {
+        Some(f) => f,
+        None => return Ok(postcode.trim().to_owned()),
+    };
+    let compact_postcode = postcode
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(|c| c.to_uppercase())
+        .collect::<String>();
+
+    formats
+        .iter()
+        .find(|(regex, _)| regex.is_match(&compact_postcode))
+        .map(|(regex, format)| {
+            regex
+                .replace(&compact_postcode, format.as_str())
+                .into_owned()
+        })
+        .ok_or_else(|| {
+            format_err!(
+                "'{}' is not a valid postcode for {}",
+                postcode,
+                country_code
+            )
+        })
+}
+
+/// Check if a country has a postcode system.
+///
+/// The unknown countries are considered to have one.
+///
+/// ```
+/// use address_formatter::uses_postcodes;
+///
+/// assert!(uses_postcodes("FR"));
+/// assert!(!uses_postcodes("HK"));
+/// assert!(!uses_postcodes("ae"));
+/// ```
+pub fn uses_postcodes(country_code: &str) -> bool {
+    CountryCode::from_str(country_code)
+        .map(|c| !COUNTRIES_WITHOUT_POSTCODES.contains(&c))
+        .unwrap_or(true)
+}
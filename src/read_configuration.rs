@@ -1,11 +1,15 @@
 use crate::formatter::{
-    CountryCode, Formatter, NewComponent, PlaceBuilder, ReplaceRule, Replacement, Rules, Template,
-    Templates,
+    Abbreviations, CountryCode, CountryOverride, Formatter, LazyRules, MinimumComponents,
+    NewComponent, PlaceBuilder, RawReplaceRules, ReplaceRule, Rules, Template, Templates,
 };
+use crate::postcode::COUNTRIES_WITHOUT_POSTCODES;
+use crate::replacement::Replacement;
 use crate::Component;
 use failure::{format_err, Error};
-use std::collections::HashMap;
+use include_dir::{include_dir, include_dir_impl};
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
+use std::sync::Arc;
 
 pub fn read_configuration() -> Formatter {
     // read all the opencage configuration
@@ -25,8 +29,10 @@ pub fn read_configuration() -> Formatter {
     let mut overrided_countries = HashMap::new();
 
     let mut fallback_templates_by_country = HashMap::new();
+    // the latin templates of the configuration override the built-in ones
+    let mut latin_templates_by_country = read_latin_templates();
     let mut rules_by_country = HashMap::new();
-    let mut templates_by_country: HashMap<CountryCode, Template> = raw_templates[0]
+    let mut templates_by_country: HashMap<CountryCode, Arc<Template>> = raw_templates[0]
         .as_hash()
         .unwrap()
         .iter()
@@ -37,7 +43,11 @@ pub fn read_configuration() -> Formatter {
         })
         .filter_map(|(country_code, v)| {
             if let Ok(fallback_template) = build_template(&v["fallback_template"]) {
-                fallback_templates_by_country.insert(country_code.clone(), fallback_template);
+                fallback_templates_by_country
+                    .insert(country_code.clone(), Arc::new(fallback_template));
+            }
+            if let Ok(latin_template) = build_template(&v["latin_address_template"]) {
+                latin_templates_by_country.insert(country_code.clone(), Arc::new(latin_template));
             }
             if let Some(parent_country) = v["use_country"]
                 .as_str()
@@ -47,59 +57,88 @@ pub fn read_configuration() -> Formatter {
                 overrided_countries.insert(country_code, (parent_country, v.clone()));
                 None
             } else {
-                let replace_rules = read_replace(&v["replace"]);
-                let post_format_replace_rules = read_replace(&v["postformat_replace"])
-                    .into_iter()
-                    .map(|r| match r {
-                        ReplaceRule::All(r) => r,
-                        _ => panic!("postformat rules cannot be applied on only one element"),
-                    })
-                    .collect();
-
-                let template = build_template(&v["address_template"]).expect(&format!(
-                    "no address_template found for country {}",
-                    country_code
-                ));
-                let rules = Rules {
-                    replace: replace_rules,
-                    postformat_replace: post_format_replace_rules,
-                    ..Default::default()
-                };
-                rules_by_country.insert(country_code.clone(), rules);
-
-                Some((country_code, template))
+                let rules = LazyRules::new(
+                    read_raw_replace(&v["replace"]),
+                    read_raw_replace(&v["postformat_replace"]),
+                );
+
+                let template = build_template(&v["address_template"]).unwrap_or_else(|_| {
+                    panic!("no address_template found for country {}", country_code)
+                });
+                rules_by_country.insert(country_code.clone(), Arc::new(rules));
+
+                Some((country_code, Arc::new(template)))
             }
         })
         .collect();
 
-    for (country_code, (parent_country_code, template)) in overrided_countries.into_iter() {
-        let overrided_template = templates_by_country[&parent_country_code].clone();
-        templates_by_country.insert(country_code.clone(), overrided_template);
-
-        let mut add_component = None;
-        if let Some(ac) = template["add_component"].as_str() {
-            let part: Vec<_> = ac.split('=').collect();
-            assert_eq!(part.len(), 2);
-            let component = Component::from_str(part[0]);
-            if let Ok(c) = component {
-                // the only valid component that can be added is 'state'
-                if c == Component::State {
-                    add_component = Some(NewComponent {
-                        component: c,
-                        new_value: part[1].to_owned(),
-                    });
-                }
+    let mut overrides_by_country = HashMap::new();
+    for (country_code, (_, template)) in overrided_countries.iter() {
+        // the parent country can also use the configuration of another country,
+        // so we follow the `use_country` chain up to a country with its own template
+        let parent_country_code = match resolve_use_country(country_code, &overrided_countries) {
+            Some(c) => c,
+            None => {
+                log::warn!(
+                    "impossible to resolve the country used by {}, it is skipped",
+                    country_code
+                );
+                continue;
             }
-        }
+        };
+        let parent_template = match templates_by_country.get(&parent_country_code) {
+            Some(t) => t.clone(),
+            None => {
+                log::warn!(
+                    "{} uses the configuration of {} that has no template, it is skipped",
+                    country_code,
+                    parent_country_code
+                );
+                continue;
+            }
+        };
+        templates_by_country.insert(country_code.clone(), parent_template);
 
-        let mut new_rules = rules_by_country
+        if let Some(parent_rules) = rules_by_country.get(&parent_country_code).cloned() {
+            rules_by_country.insert(country_code.clone(), parent_rules);
+        }
+        if let Some(parent_fallback) = fallback_templates_by_country
             .get(&parent_country_code)
-            .map(|r| r.clone())
-            .unwrap_or_else(|| Rules::default());
-        new_rules.change_country_code = Some(parent_country_code.as_str().to_owned());
-        new_rules.change_country = template["change_country"].as_str().map(|s| s.to_string());
-        new_rules.add_component = add_component;
-        rules_by_country.insert(country_code.clone(), new_rules);
+            .cloned()
+        {
+            fallback_templates_by_country.insert(country_code.clone(), parent_fallback);
+        }
+        if !latin_templates_by_country.contains_key(country_code) {
+            if let Some(parent_latin) = latin_templates_by_country
+                .get(&parent_country_code)
+                .cloned()
+            {
+                latin_templates_by_country.insert(country_code.clone(), parent_latin);
+            }
+        }
+
+        let add_component = template["add_component"].as_str().and_then(|ac| {
+            let part: Vec<_> = ac.splitn(2, '=').collect();
+            match (part.first().map(|c| Component::from_str(c)), part.get(1)) {
+                (Some(Ok(component)), Some(new_value)) => Some(NewComponent {
+                    component,
+                    new_value: new_value.to_string(),
+                }),
+                _ => {
+                    log::warn!("invalid add_component '{}' for {}", ac, country_code);
+                    None
+                }
+            }
+        });
+
+        overrides_by_country.insert(
+            country_code.clone(),
+            CountryOverride {
+                change_country: template["change_country"].as_str().map(|s| s.to_string()),
+                change_country_code: Some(parent_country_code.as_str().to_owned()),
+                add_component,
+            },
+        );
     }
 
     let state_codes_file = include_str!("../address-formatting/conf/state_codes.yaml");
@@ -112,7 +151,7 @@ pub fn read_configuration() -> Formatter {
                 (
                     (
                         CountryCode::from_str(&country).expect("invalid country code"),
-                        state_name,
+                        state_name.to_uppercase(),
                     ),
                     state_code,
                 )
@@ -129,7 +168,7 @@ pub fn read_configuration() -> Formatter {
                 (
                     (
                         CountryCode::from_str(&country).expect("invalid country code"),
-                        county_name,
+                        county_name.to_uppercase(),
                     ),
                     county_code,
                 )
@@ -137,21 +176,124 @@ pub fn read_configuration() -> Formatter {
         })
         .collect();
 
+    let minimum_components_file = include_str!("../data/minimum_components.yaml");
+    let mut minimum_components: HashMap<String, Vec<Vec<String>>> =
+        serde_yaml::from_str(minimum_components_file)
+            .expect("invalid minimum_components.yaml file");
+    let default_minimum_components = minimum_components
+        .remove("default")
+        .map(build_minimum_components)
+        .unwrap_or_default();
+    let without_postcodes_minimum_components = minimum_components
+        .remove("without_postcodes")
+        .map(build_minimum_components);
+    let mut minimum_components_by_country: HashMap<_, _> = minimum_components
+        .into_iter()
+        .map(|(country, sets)| {
+            (
+                CountryCode::from_str(&country).expect("invalid country code"),
+                build_minimum_components(sets),
+            )
+        })
+        .collect();
+    // the countries without postcode system cannot rely on the postcode
+    if let Some(sets) = without_postcodes_minimum_components {
+        for country_code in COUNTRIES_WITHOUT_POSTCODES.iter() {
+            minimum_components_by_country
+                .entry(country_code.clone())
+                .or_insert_with(|| sets.clone());
+        }
+    }
+
     let templates = Templates {
         default_template,
         fallback_template,
         templates_by_country,
         fallback_templates_by_country,
         rules_by_country,
-        fallback_rules: Rules::default(),
+        overrides_by_country,
+        fallback_rules: LazyRules::default(),
+        default_minimum_components,
+        minimum_components_by_country,
+        latin_templates_by_country,
     };
     Formatter {
         templates,
         state_codes,
         county_codes,
+        country_to_lang: read_country_to_lang(),
+        abbreviations: read_abbreviations(),
     }
 }
 
+fn read_latin_templates() -> HashMap<CountryCode, Arc<Template>> {
+    let latin_templates_file = include_str!("../data/latin_templates.yaml");
+    let latin_templates: HashMap<String, String> =
+        serde_yaml::from_str(latin_templates_file).expect("invalid latin_templates.yaml file");
+    latin_templates
+        .into_iter()
+        // the keys that are not countries are the yaml anchors
+        .filter_map(|(country, template)| {
+            CountryCode::from_str(&country)
+                .ok()
+                .map(|c| (c, Arc::new(Template::new(&template))))
+        })
+        .collect()
+}
+
+fn read_country_to_lang() -> HashMap<CountryCode, Vec<String>> {
+    let country_to_lang_file = include_str!("../address-formatting/conf/country2lang.yaml");
+    let country_to_lang: HashMap<String, String> =
+        serde_yaml::from_str(country_to_lang_file).expect("invalid country2lang.yaml file");
+    country_to_lang
+        .into_iter()
+        .filter_map(|(country, langs)| {
+            CountryCode::from_str(&country)
+                .map_err(|e| log::warn!("invalid country in country2lang.yaml: {}", e))
+                .ok()
+                .map(|c| (c, langs.split(',').map(|l| l.trim().to_owned()).collect()))
+        })
+        .collect()
+}
+
+fn read_abbreviations() -> HashMap<String, Abbreviations> {
+    let abbreviations_dir = include_dir!("./address-formatting/conf/abbreviations");
+    abbreviations_dir
+        .files()
+        .iter()
+        .filter_map(|f| {
+            let lang = f.path().file_stem()?.to_str()?.to_owned();
+            let abbreviations: HashMap<String, HashMap<String, String>> =
+                serde_yaml::from_str(f.contents_utf8()?)
+                    .map_err(|e| log::warn!("invalid abbreviations for {}: {}", lang, e))
+                    .ok()?;
+            let words = abbreviations
+                .into_iter()
+                .filter_map(|(component, words)| match Component::from_str(&component) {
+                    Ok(c) => Some((c, words)),
+                    Err(_) => {
+                        log::info!("unknown component {} in {} abbreviations", component, lang);
+                        None
+                    }
+                })
+                .collect();
+            Some((lang, Abbreviations::new(words)))
+        })
+        .collect()
+}
+
+fn build_minimum_components(sets: Vec<Vec<String>>) -> MinimumComponents {
+    MinimumComponents::new(
+        sets.iter()
+            .map(|set| {
+                set.iter()
+                    .map(|c| Component::from_str(c).expect("invalid component"))
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
 pub fn read_place_builder_configuration() -> PlaceBuilder {
     let component_file = include_str!("../address-formatting/conf/components.yaml");
     let raw_components = yaml_rust::YamlLoader::load_from_str(component_file)
@@ -175,6 +317,24 @@ pub fn read_place_builder_configuration() -> PlaceBuilder {
     PlaceBuilder { component_aliases }
 }
 
+/// follow the `use_country` chain of a country up to the first country that does not use another country's configuration
+///
+/// Returns None if there is a cycle in the chain
+fn resolve_use_country(
+    country_code: &CountryCode,
+    overrided_countries: &HashMap<CountryCode, (CountryCode, yaml_rust::Yaml)>,
+) -> Option<CountryCode> {
+    let mut visited = HashSet::new();
+    let mut current = country_code;
+    while let Some((parent, _)) = overrided_countries.get(current) {
+        if !visited.insert(current) {
+            return None;
+        }
+        current = parent;
+    }
+    Some(current.clone())
+}
+
 fn build_template(yaml_value: &yaml_rust::Yaml) -> Result<Template, Error> {
     let addr_template = yaml_value
         .as_str()
@@ -183,7 +343,7 @@ fn build_template(yaml_value: &yaml_rust::Yaml) -> Result<Template, Error> {
     Ok(Template::new(addr_template))
 }
 
-fn read_replace(yaml_rules: &yaml_rust::Yaml) -> Vec<ReplaceRule> {
+fn read_raw_replace(yaml_rules: &yaml_rust::Yaml) -> RawReplaceRules {
     yaml_rules
         .as_vec()
         .map(|v| {
@@ -192,43 +352,113 @@ fn read_replace(yaml_rules: &yaml_rust::Yaml) -> Vec<ReplaceRule> {
                     let r = r.as_vec().expect("replace should be a list");
                     assert_eq!(r.len(), 2);
 
-                    let first_val = r[0].as_str().expect("invalid replace rule");
-                    if first_val.contains('=') {
-                        // it's a replace on only one component
-                        // the rules is written 'component=<string_to_replace'
-                        let parts = first_val.split('=').collect::<Vec<_>>();
-                        let component = Component::from_str(parts[0]).expect(&format!(
-                            "in replace '{}' is not a valid component",
-                            parts[0]
-                        ));
-                        ReplaceRule::Component((
-                            component,
-                            Replacement {
-                                regex: regex::RegexBuilder::new(parts[1])
-                                    .multi_line(true)
-                                    .build()
-                                    .expect("invalid regex"),
-                                replacement_value: r[1]
-                                    .as_str()
-                                    .expect("invalid replace rule")
-                                    .to_owned(),
-                            },
-                        ))
-                    } else {
-                        // it's a replace for all components
-                        ReplaceRule::All(Replacement {
-                            regex: regex::RegexBuilder::new(first_val)
-                                .multi_line(true)
-                                .build()
-                                .expect("invalid regex"),
-                            replacement_value: r[1]
-                                .as_str()
-                                .expect("invalid replace rule")
-                                .to_owned(),
-                        })
-                    }
+                    (
+                        r[0].as_str().expect("invalid replace rule").to_owned(),
+                        r[1].as_str().expect("invalid replace rule").to_owned(),
+                    )
                 })
                 .collect()
         })
-        .unwrap_or_else(|| vec![])
+        .unwrap_or_default()
+}
+
+/// compile the raw replacement rules of a country
+pub fn build_rules(
+    replace: &[(String, String)],
+    postformat_replace: &[(String, String)],
+) -> Result<Rules, Error> {
+    let replace = build_replace_rules(replace)?;
+    let postformat_replace = build_replace_rules(postformat_replace)?;
+    Ok(Rules {
+        replace,
+        postformat_replace,
+    })
+}
+
+/// build the replace rules
+/// the rules not supported by the regex crate (like a regex with lookaround without the `fancy-regex` feature)
+/// are skipped, the other rules of the country are still applied
+fn build_replace_rules(rules: &[(String, String)]) -> Result<Vec<ReplaceRule>, Error> {
+    rules
+        .iter()
+        .filter_map(|(from, to)| build_replace(from, to).transpose())
+        .collect()
+}
+
+fn build_replace(from: &str, to: &str) -> Result<Option<ReplaceRule>, Error> {
+    if from.contains('=') {
+        // it's a replace on only one component
+        // the rules is written 'component=<string_to_replace'
+        let parts = from.splitn(2, '=').collect::<Vec<_>>();
+        let component = Component::from_str(parts[0])
+            .map_err(|_| format_err!("in replace '{}' is not a valid component", parts[0]))?;
+        Ok(build_replacement(parts[1], to)?
+            .map(|r| ReplaceRule::Component((component, parts[1].to_owned(), r))))
+    } else {
+        // it's a replace for all components
+        Ok(build_replacement(from, to)?.map(ReplaceRule::All))
+    }
+}
+
+fn build_replacement(from: &str, to: &str) -> Result<Option<Replacement>, Error> {
+    Replacement::new(from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrided_countries(
+        chain: &[(&str, &str)],
+    ) -> HashMap<CountryCode, (CountryCode, yaml_rust::Yaml)> {
+        chain
+            .iter()
+            .map(|(country, parent)| {
+                (
+                    CountryCode::from_str(country).unwrap(),
+                    (
+                        CountryCode::from_str(parent).unwrap(),
+                        yaml_rust::Yaml::Null,
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chained_use_country() {
+        // AX uses FI that uses SE
+        let countries = overrided_countries(&[("AX", "FI"), ("FI", "SE")]);
+        let se = CountryCode::from_str("SE").unwrap();
+        assert_eq!(
+            resolve_use_country(&CountryCode::from_str("AX").unwrap(), &countries),
+            Some(se.clone())
+        );
+        assert_eq!(
+            resolve_use_country(&CountryCode::from_str("FI").unwrap(), &countries),
+            Some(se.clone())
+        );
+        assert_eq!(resolve_use_country(&se, &countries), Some(se));
+    }
+
+    #[test]
+    fn cyclic_use_country() {
+        let countries = overrided_countries(&[("AA", "BB"), ("BB", "CC"), ("CC", "AA")]);
+        assert_eq!(
+            resolve_use_country(&CountryCode::from_str("AA").unwrap(), &countries),
+            None
+        );
+        // a country using itself
+        let countries = overrided_countries(&[("AA", "AA")]);
+        assert_eq!(
+            resolve_use_country(&CountryCode::from_str("AA").unwrap(), &countries),
+            None
+        );
+        // a country using a cycle
+        let countries = overrided_countries(&[("DD", "AA"), ("AA", "BB"), ("BB", "AA")]);
+        assert_eq!(
+            resolve_use_country(&CountryCode::from_str("DD").unwrap(), &countries),
+            None
+        );
+    }
 }
@@ -0,0 +1,120 @@
+use crate::country::get_by_language;
+use crate::{Component, Place};
+use failure::{Error, ResultExt};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+lazy_static::lazy_static! {
+    static ref BUILTIN_REGION_NAMES: RegionNames =
+        RegionNames::from_yaml(include_str!("../data/region_names.yaml")).expect("invalid region_names.yaml file");
+}
+
+/// The names of the states and regions by language, used to translate the `State` of a
+/// [`Place`](struct.Place.html) to the output language of the [`Configuration`](struct.Configuration.html).
+///
+/// The subdivisions are identified by their [ISO 3166-2](https://en.wikipedia.org/wiki/ISO_3166-2) code
+/// (like "DE-BY" for Bavaria).
+/// The default table contains the german and austrian states and the belgian regions and provinces,
+/// it can be completed or replaced by the caller.
+///
+/// ```
+/// use address_formatter::RegionNames;
+///
+/// let names = RegionNames::default().with("IT-25", "en", "Lombardy").with("IT-25", "it", "Lombardia");
+///
+/// assert_eq!(names.name("DE-BY", "en"), Some("Bavaria"));
+/// assert_eq!(names.name("IT-25", "en"), Some("Lombardy"));
+/// assert_eq!(names.find_code("IT", "lombardia"), Some("IT-25"));
+/// assert_eq!(names.find_code("BE", "Lüttich"), Some("BE-WLG"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RegionNames(Arc<RegionTable>);
+
+#[derive(Debug, Clone, Default)]
+struct RegionTable {
+    names_by_code: HashMap<String, HashMap<String, String>>,
+    // the codes by (country code, lowercase name), for the names in all the languages
+    codes_by_name: HashMap<(String, String), String>,
+}
+
+impl Default for RegionNames {
+    fn default() -> Self {
+        BUILTIN_REGION_NAMES.clone()
+    }
+}
+
+impl RegionNames {
+    /// A table without any name
+    pub fn empty() -> Self {
+        RegionNames(Arc::new(RegionTable::default()))
+    }
+
+    /// Read a table from a yaml document, like:
+    ///
+    /// ```yaml
+    /// DE-BY:
+    ///   de: Bayern
+    ///   en: Bavaria
+    /// ```
+    pub fn from_yaml(yaml: &str) -> Result<Self, Error> {
+        let names: HashMap<String, HashMap<String, String>> =
+            serde_yaml::from_str(yaml).context("invalid region names")?;
+        let mut region_names = Self::empty();
+        for (code, names_by_language) in names {
+            for (language, name) in names_by_language {
+                region_names = region_names.with(&code, &language, &name);
+            }
+        }
+        Ok(region_names)
+    }
+
+    /// Add (or replace) the name of a subdivision in a language
+    pub fn with(mut self, subdivision_code: &str, language: &str, name: &str) -> Self {
+        let table = Arc::make_mut(&mut self.0);
+        let code = subdivision_code.to_uppercase();
+        if let Some(country_code) = code.split('-').next() {
+            table
+                .codes_by_name
+                .insert((country_code.to_owned(), name.to_lowercase()), code.clone());
+        }
+        table
+            .names_by_code
+            .entry(code)
+            .or_default()
+            .insert(language.to_lowercase(), name.to_owned());
+        self
+    }
+
+    /// The name of a subdivision in a language
+    pub fn name(&self, subdivision_code: &str, language: &str) -> Option<&str> {
+        self.0
+            .names_by_code
+            .get(&subdivision_code.to_uppercase())
+            .and_then(|names| get_by_language(names, language))
+            .map(|n| n.as_str())
+    }
+
+    /// The code of a subdivision of a country from its name in any language
+    pub fn find_code(&self, country_code: &str, name: &str) -> Option<&str> {
+        self.0
+            .codes_by_name
+            .get(&(country_code.to_uppercase(), name.to_lowercase()))
+            .map(|c| c.as_str())
+    }
+
+    /// translate the state of the place, it is kept as is if its name is not known
+    pub(crate) fn translate_state(&self, addr: &mut Place, language: &str) {
+        let (country_code, state) = match (&addr[Component::CountryCode], &addr[Component::State]) {
+            (Some(c), Some(s)) => (c.to_uppercase(), s),
+            _ => return,
+        };
+        let code = addr[Component::StateCode]
+            .as_ref()
+            .map(|state_code| format!("{}-{}", country_code, state_code.to_uppercase()))
+            .filter(|code| self.0.names_by_code.contains_key(code))
+            .or_else(|| self.find_code(&country_code, state).map(|c| c.to_owned()));
+        if let Some(name) = code.and_then(|c| self.name(&c, language)) {
+            addr[Component::State] = Some(name.to_owned());
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Replacements of the opencage configuration
+//!
+//! The opencage rules are written for the perl implementation: the regexes can be perl regexes
+//! and the replacement values can reference the captures with `$1` or `\1`.
+//!
+//! By default the regexes are compiled with the [regex](https://docs.rs/regex) crate, that has no lookaround nor backreferences.
+//! The rules needing them are skipped, unless the `fancy-regex` feature is enabled:
+//! the regexes are then compiled with [fancy-regex](https://docs.rs/fancy-regex) that supports them.
+//!
+//! Like in the perl and javascript implementations, only the first match of a regex is replaced.
+
+use failure::{format_err, Error};
+use std::borrow::Cow;
+
+#[cfg(not(feature = "fancy-regex"))]
+type Regex = regex::Regex;
+#[cfg(feature = "fancy-regex")]
+type Regex = fancy_regex::Regex;
+
+/// Represents a Regex and the value to replace the regex matches with
+#[derive(Debug, Clone)]
+pub(crate) struct Replacement {
+    regex: Regex,
+    /// value to replace the match with, as written in the configuration
+    pub replacement_value: String,
+    /// value to replace the match with, with the captures written like `${1}`
+    expanded_value: String,
+}
+
+impl Replacement {
+    /// Build a replacement from a perl regex
+    ///
+    /// Returns None if the regex is valid but not supported by the regex crate
+    /// (like the lookaround without the `fancy-regex` feature), and an error if the regex is invalid
+    pub fn new(regex: &str, replacement_value: &str) -> Result<Option<Self>, Error> {
+        // the regexes are multi lines
+        let pattern = format!("(?m){}", regex);
+        let compiled_regex = match Regex::new(&pattern) {
+            Ok(r) => r,
+            Err(e) if is_unsupported_syntax(&e) => {
+                log::warn!("regex '{}' is not supported, skipping it: {}", regex, e);
+                return Ok(None);
+            }
+            Err(e) => return Err(format_err!("invalid regex '{}': {}", regex, e)),
+        };
+        Ok(Some(Replacement {
+            regex: compiled_regex,
+            replacement_value: replacement_value.to_owned(),
+            expanded_value: translate_perl_replacement(replacement_value),
+        }))
+    }
+
+    /// replace the first match of the regex in the text
+    pub fn replace<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        let captures = match self.captures(text) {
+            Some(c) => c,
+            None => return Cow::Borrowed(text),
+        };
+        let whole_match = captures.get(0).expect("a capture always has a whole match");
+        let mut res = String::with_capacity(text.len());
+        res.push_str(&text[..whole_match.start()]);
+        captures.expand(&self.expanded_value, &mut res);
+        res.push_str(&text[whole_match.end()..]);
+        Cow::Owned(res)
+    }
+
+    #[cfg(not(feature = "fancy-regex"))]
+    fn captures<'t>(&self, text: &'t str) -> Option<regex::Captures<'t>> {
+        self.regex.captures(text)
+    }
+
+    #[cfg(feature = "fancy-regex")]
+    fn captures<'t>(&self, text: &'t str) -> Option<fancy_regex::Captures<'t>> {
+        // the backtracking can fail on some pathological text, the replacement is then not applied
+        self.regex
+            .captures(text)
+            .map_err(|e| log::warn!("impossible to apply regex '{}': {}", self.regex, e))
+            .ok()
+            .flatten()
+    }
+}
+
+/// true if the regex uses the perl syntax that the regex crate does not support
+#[cfg(not(feature = "fancy-regex"))]
+fn is_unsupported_syntax(error: &regex::Error) -> bool {
+    let message = error.to_string();
+    message.contains("look-around") || message.contains("backreferences are not supported")
+}
+
+#[cfg(feature = "fancy-regex")]
+fn is_unsupported_syntax(_error: &fancy_regex::Error) -> bool {
+    false
+}
+
+/// translate a perl replacement value to the regex crates syntax:
+/// `\1` and `$1` are the first capture (written `${1}` to avoid any ambiguity with the following characters)
+/// and the other `$` are escaped
+fn translate_perl_replacement(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '$' if chars.peek().filter(|n| n.is_ascii_digit()).is_some() => {
+                res.push_str("${");
+                while let Some(d) = chars.peek().filter(|n| n.is_ascii_digit()) {
+                    res.push(*d);
+                    chars.next();
+                }
+                res.push('}');
+            }
+            '$' => res.push_str("$$"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn perl_captures() {
+        assert_eq!(translate_perl_replacement(r"\1"), "${1}");
+        assert_eq!(translate_perl_replacement("$1"), "${1}");
+        assert_eq!(translate_perl_replacement(r"\12 $2a"), "${12} ${2}a");
+        assert_eq!(translate_perl_replacement("no capture"), "no capture");
+    }
+
+    #[test]
+    fn escaped_dollar() {
+        assert_eq!(translate_perl_replacement("$"), "$$");
+        assert_eq!(translate_perl_replacement("US$ $name"), "US$$ $$name");
+    }
+
+    #[test]
+    fn first_match_replaced() {
+        let r = Replacement::new(r"(\d+) Street", "Street $1")
+            .unwrap()
+            .unwrap();
+        assert_eq!(r.replace("12 Street, 14 Street"), "Street 12, 14 Street");
+        assert_eq!(r.replace("Avenue"), "Avenue");
+    }
+
+    #[test]
+    fn invalid_regex() {
+        assert!(Replacement::new("a(", "b").is_err());
+    }
+
+    #[cfg(not(feature = "fancy-regex"))]
+    #[test]
+    fn unsupported_lookahead() {
+        assert!(Replacement::new(r"Saint(?= )", "St").unwrap().is_none());
+        assert!(Replacement::new(r"(a)\1", "a").unwrap().is_none());
+    }
+
+    #[cfg(feature = "fancy-regex")]
+    #[test]
+    fn lookahead() {
+        let r = Replacement::new(r"Saint(?= )", "St").unwrap().unwrap();
+        assert_eq!(r.replace("Saint Denis"), "St Denis");
+        assert_eq!(r.replace("Saint-Denis"), "Saint-Denis");
+        let r = Replacement::new(r"(\w+) \1", "$1").unwrap().unwrap();
+        assert_eq!(r.replace("Paris Paris"), "Paris");
+    }
+}
@@ -0,0 +1,57 @@
+use crate::cleaning::CleaningChange;
+use crate::formatter::{TemplateKind, TemplateScript};
+use crate::Component;
+use serde::Serialize;
+
+/// What happened to the data of a [`Place`](struct.Place.html) during its formatting.
+///
+/// It is returned by [`Formatter::format_with_report`](struct.Formatter.html#method.format_with_report)
+/// alongside the formatted text, to spot the suspicious places without comparing texts.
+#[derive(Debug, Clone, Serialize)]
+pub struct FormatReport {
+    /// the country used to format the place
+    pub country_code: Option<String>,
+    /// the kind of template used to format the place
+    pub template_kind: TemplateKind,
+    /// the script of the template used to format the place
+    pub template_script: TemplateScript,
+    /// the values changed or removed by the sanity cleaning
+    pub cleaned: Vec<CleaningChange>,
+    /// the postcode, if the country has no postcode system, or if it is not valid for the country
+    /// (only checked when the postcodes are normalized in the [`Configuration`](struct.Configuration.html))
+    pub invalid_postcode: Option<String>,
+    /// the values rewritten by the replace rules of the country
+    pub rewritten: Vec<ComponentRewrite>,
+    /// the components of the place not found in the formatted text (their data are lost in the output)
+    pub unused_components: Vec<Component>,
+    /// the duplicated parts of the formatted text that have been removed
+    pub collapsed_duplicates: Vec<String>,
+    /// true if some characters could not be transliterated to the latin script
+    /// (like the kanji of the japanese places, kept as is)
+    #[cfg(feature = "transliteration")]
+    pub untransliterated: bool,
+}
+
+/// A value rewritten by a replace rule
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentRewrite {
+    /// rewritten component
+    pub component: Component,
+    /// value before the replace rules
+    pub old_value: String,
+    /// value after the replace rules
+    pub new_value: String,
+}
+
+impl FormatReport {
+    /// true if the place did not have enough components for the main template of its country
+    pub fn used_fallback_template(&self) -> bool {
+        self.template_kind == TemplateKind::Fallback
+    }
+
+    /// true if some data of the place have been removed, either by the sanity cleaning
+    /// (or the removal of an invalid postcode) or because the template did not use them
+    pub fn has_data_loss(&self) -> bool {
+        self.cleaned.iter().any(|c| c.is_removal()) || !self.unused_components.is_empty()
+    }
+}
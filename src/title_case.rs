@@ -0,0 +1,225 @@
+use crate::country::get_by_language;
+use crate::{Component, Place};
+use itertools::Itertools;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+lazy_static::lazy_static! {
+    static ref CASING_RULES: HashMap<String, CasingRules> =
+        serde_yaml::from_str(include_str!("../data/title_case.yaml")).expect("invalid title_case.yaml file");
+    // only the small roman numerals, to avoid the words like "mix" or "dix"
+    static ref ROMAN_NUMERAL: Regex = Regex::new(r"^c{0,3}(xc|xl|l?x{0,3})(ix|iv|v?i{0,3})$").unwrap();
+    // the short codes, like the state "NY"
+    static ref SHORT_CODE: Regex = Regex::new(r"^[A-Z]{1,4}$").unwrap();
+    // the road references, like "US 101", "RN 7" or "I-95"
+    static ref ROAD_REF: Regex = Regex::new(r"^[A-Z]{1,4}[ -]?[0-9]+[A-Z]?$").unwrap();
+    // the alphanumeric references in a name, like "D113" in "Route D113"
+    static ref ALPHANUMERIC_REF: Regex = Regex::new(r"^[A-Z]+-?[0-9]+[A-Z]?$").unwrap();
+}
+
+/// The components whose value can be a code, like the state "NY"
+const CODE_COMPONENTS: [Component; 4] = [
+    Component::County,
+    Component::StateDistrict,
+    Component::State,
+    Component::Region,
+];
+
+/// The usual words written like roman numerals
+const NOT_ROMAN_NUMERALS: [&str; 2] = ["ci", "li"];
+
+/// The components written in title case
+const TITLE_CASE_COMPONENTS: [Component; 12] = [
+    Component::Road,
+    Component::Neighbourhood,
+    Component::Suburb,
+    Component::CityDistrict,
+    Component::Village,
+    Component::Town,
+    Component::City,
+    Component::County,
+    Component::StateDistrict,
+    Component::State,
+    Component::Region,
+    Component::Island,
+];
+
+#[derive(Debug, Default, Deserialize)]
+struct CasingRules {
+    #[serde(default)]
+    lowercase: Vec<String>,
+    #[serde(default)]
+    prefixes: Vec<String>,
+    #[serde(default)]
+    uppercase: Vec<String>,
+}
+
+/// Write a text in title case, following the rules of a language.
+///
+/// The particles of the language are written in lowercase (except at the start of the text),
+/// and the roman numerals in uppercase.
+///
+/// ```
+/// use address_formatter::title_case;
+///
+/// assert_eq!(title_case("RUE DE L'ÉGLISE", "fr"), "Rue de l'Église");
+/// assert_eq!(title_case("saint-jean-de-luz", "fr"), "Saint-Jean-de-Luz");
+/// assert_eq!(title_case("PLACE LOUIS XIV", "fr"), "Place Louis XIV");
+/// assert_eq!(title_case("AVENUE LI MIX", "fr"), "Avenue Li Mix");
+/// assert_eq!(title_case("VAN DER DUYNSTRAAT", "nl"), "Van der Duynstraat");
+/// assert_eq!(title_case("mcdonald street", "en"), "McDonald Street");
+/// ```
+pub fn title_case(text: &str, language: &str) -> String {
+    let default_rules = CasingRules::default();
+    let rules = get_by_language(&CASING_RULES, language).unwrap_or(&default_rules);
+    text.split(' ')
+        .enumerate()
+        .map(|(i, word)| {
+            // the hyphenated words are cased part by part
+            word.to_lowercase()
+                .split('-')
+                .enumerate()
+                .map(|(j, part)| case_word(part, i == 0 && j == 0, rules))
+                .join("-")
+        })
+        .join(" ")
+}
+
+/// write the components in title case, if they are all in uppercase or all in lowercase
+/// (the case of a single value is not enough, an uppercase value can be an acronym or a code in a mixed case place)
+/// the rules of the language of the place are used, and the codes and the road references are kept in uppercase
+pub(crate) fn title_case_place(addr: &mut Place, languages: &[String]) {
+    if !has_uniform_case(
+        TITLE_CASE_COMPONENTS
+            .iter()
+            .filter_map(|c| addr[*c].as_deref()),
+    ) {
+        return;
+    }
+    let language = place_language(addr, languages);
+    for c in TITLE_CASE_COMPONENTS.iter() {
+        if let Some(value) = &addr[*c] {
+            let is_code = CODE_COMPONENTS.contains(c) && SHORT_CODE.is_match(value);
+            if !is_code && !ROAD_REF.is_match(value) {
+                addr[*c] = Some(title_case_keeping_refs(value, language));
+            }
+        }
+    }
+}
+
+/// title case a value, but keep its alphanumeric references as they are
+fn title_case_keeping_refs(value: &str, language: &str) -> String {
+    value
+        .split(' ')
+        .zip(title_case(value, language).split(' '))
+        .map(|(word, cased_word)| {
+            if ALPHANUMERIC_REF.is_match(word) {
+                word
+            } else {
+                cased_word
+            }
+        })
+        .join(" ")
+}
+
+/// the language of the place, among the languages of its country having some rules:
+/// the one with the most particles in the place (like "de la" in french),
+/// or the first one if the particles do not tell the language
+fn place_language<'a>(addr: &Place, languages: &'a [String]) -> &'a str {
+    let words = TITLE_CASE_COMPONENTS
+        .iter()
+        .filter_map(|c| addr[*c].as_ref())
+        .flat_map(|v| {
+            v.to_lowercase()
+                .split([' ', '-'])
+                .map(|w| w.to_owned())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    languages
+        .iter()
+        .filter_map(|l| get_by_language(&CASING_RULES, l).map(|rules| (l, rules)))
+        // the first language is kept on a tie
+        .rev()
+        .max_by_key(|(_, rules)| rules.count_particles(&words))
+        .map(|(l, _)| l.as_str())
+        .unwrap_or("")
+}
+
+impl CasingRules {
+    fn count_particles(&self, words: &[String]) -> usize {
+        words
+            .iter()
+            .filter(|w| {
+                self.lowercase.iter().any(|p| {
+                    *w == p
+                        || (p.ends_with('\'') && strip_prefix(w, p).is_some_and(|r| !r.is_empty()))
+                })
+            })
+            .count()
+    }
+}
+
+fn has_uniform_case<'a>(values: impl Iterator<Item = &'a str>) -> bool {
+    let cased_letters = values
+        .flat_map(|v| v.chars())
+        .filter(|c| c.is_uppercase() || c.is_lowercase())
+        .collect::<Vec<_>>();
+    cased_letters.len() > 1
+        && (cased_letters.iter().all(|c| c.is_uppercase())
+            || cased_letters.iter().all(|c| c.is_lowercase()))
+}
+
+fn case_word(word: &str, is_first: bool, rules: &CasingRules) -> String {
+    if rules.uppercase.iter().any(|w| w == word) {
+        return word.to_uppercase();
+    }
+    if !is_first {
+        if rules.lowercase.iter().any(|w| w == word) {
+            return word.to_owned();
+        }
+        // the roman numerals follow a name, like "Louis XIV"
+        // (the single letters are only capitalized)
+        if word.chars().count() > 1
+            && ROMAN_NUMERAL.is_match(word)
+            && !NOT_ROMAN_NUMERALS.contains(&word)
+        {
+            return word.to_uppercase();
+        }
+    }
+    // the elided particles, like "l'" in "l'église"
+    for particle in rules.lowercase.iter().filter(|p| p.ends_with('\'')) {
+        if let Some(rest) = strip_prefix(word, particle).filter(|r| !r.is_empty()) {
+            let particle = &word[..word.len() - rest.len()];
+            let particle = if is_first {
+                capitalize(particle)
+            } else {
+                particle.to_owned()
+            };
+            return particle + &capitalize(rest);
+        }
+    }
+    // the prefixes followed by a capital letter, like "mc" in "mcdonald"
+    for prefix in &rules.prefixes {
+        if let Some(rest) = strip_prefix(word, prefix).filter(|r| r.chars().count() > 1) {
+            let prefix = &word[..word.len() - rest.len()];
+            return capitalize(prefix) + &capitalize(rest);
+        }
+    }
+    capitalize(word)
+}
+
+/// strip a prefix, the apostrophes can be typographic ones
+fn strip_prefix<'a>(word: &'a str, prefix: &str) -> Option<&'a str> {
+    word.strip_prefix(prefix)
+        .or_else(|| word.strip_prefix(prefix.replace('\'', "’").as_str()))
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
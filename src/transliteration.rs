@@ -0,0 +1,96 @@
+use unicode_script::{Script, UnicodeScript};
+
+/// Write a text in the latin script.
+///
+/// The latin letters (with their diacritics) are kept, the other scripts are transliterated
+/// with the tables of [deunicode](https://docs.rs/deunicode).
+/// The chinese characters are transliterated with their mandarin pronunciation
+/// (when formatting a japanese place, the kanji are kept, see
+/// [`Configuration::transliterate`](struct.Configuration.html#structfield.transliterate)).
+///
+/// ```
+/// use address_formatter::transliterate;
+///
+/// assert_eq!(transliterate("Москва"), "Moskva");
+/// assert_eq!(transliterate("Αθήνα"), "Athena");
+/// assert_eq!(transliterate("서울"), "Seoul");
+/// assert_eq!(transliterate("Zürich"), "Zürich");
+/// assert_eq!(transliterate("北京市\n中国"), "Bei Jing Shi\nZhong Guo");
+/// ```
+pub fn transliterate(text: &str) -> String {
+    transliterate_with(text, false)
+}
+
+/// write a text in the latin script, keeping the chinese characters if `keep_han` is true
+/// (the japanese reading of the kanji cannot be found from the characters)
+pub(crate) fn transliterate_with(text: &str, keep_han: bool) -> String {
+    let mut transliterated = String::with_capacity(text.len());
+    let mut run = String::new();
+    for c in text.chars() {
+        if is_latin(c) || (keep_han && is_han(c)) {
+            push_run(&mut transliterated, &run, Some(c));
+            run.clear();
+            transliterated.push(c);
+        } else {
+            run.push(c);
+        }
+    }
+    push_run(&mut transliterated, &run, None);
+    transliterated
+}
+
+pub(crate) fn is_han(c: char) -> bool {
+    c.script() == Script::Han
+}
+
+fn is_latin(c: char) -> bool {
+    c.is_ascii()
+        || c.is_whitespace()
+        // latin-1 supplement, latin extended-A and B
+        || ('\u{00A0}'..='\u{024F}').contains(&c)
+        // combining diacritical marks
+        || ('\u{0300}'..='\u{036F}').contains(&c)
+        // latin extended additional
+        || ('\u{1E00}'..='\u{1EFF}').contains(&c)
+        // general punctuation
+        || ('\u{2000}'..='\u{206F}').contains(&c)
+}
+
+/// add a transliterated run of characters, separated by a space from the surrounding words
+fn push_run(transliterated: &mut String, run: &str, next_char: Option<char>) {
+    if run.is_empty() {
+        return;
+    }
+    // the korean transliterations are in lowercase
+    let run = if run.chars().any(|c| c.script() == Script::Hangul) {
+        capitalize_words(&deunicode::deunicode(run))
+    } else {
+        deunicode::deunicode(run)
+    };
+    let run = run.trim();
+    if run.is_empty() {
+        return;
+    }
+    let is_word_char = |c: Option<char>| c.filter(|c| c.is_alphanumeric()).is_some();
+    if is_word_char(transliterated.chars().last()) && is_word_char(run.chars().next()) {
+        transliterated.push(' ');
+    }
+    transliterated.push_str(run);
+    if is_word_char(run.chars().last()) && is_word_char(next_char) {
+        transliterated.push(' ');
+    }
+}
+
+fn capitalize_words(text: &str) -> String {
+    let mut capitalized = String::with_capacity(text.len());
+    let mut word_start = true;
+    for c in text.chars() {
+        if word_start {
+            capitalized.extend(c.to_uppercase());
+        } else {
+            capitalized.push(c);
+        }
+        word_start = !c.is_alphanumeric();
+    }
+    capitalized
+}
@@ -0,0 +1,242 @@
+use address_formatter::{Component, Configuration, Place, PlaceBuilder, FORMATTER};
+use proptest::prelude::*;
+use regex::Regex;
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+use yaml_rust::{Yaml, YamlLoader};
+
+lazy_static::lazy_static! {
+    static ref PLACE_BUILDER: PlaceBuilder = PlaceBuilder::default();
+    /// some countries, with the components referenced by their template for a place in the latin script
+    static ref COUNTRIES_REFERENCED_COMPONENTS: Vec<(&'static str, Vec<Component>)> = {
+        let countries_conf = YamlLoader::load_from_str(include_str!(
+            "../address-formatting/conf/countries/worldwide.yaml"
+        ))
+        .unwrap()
+        .remove(0);
+        let latin_templates = YamlLoader::load_from_str(include_str!("../data/latin_templates.yaml"))
+            .unwrap()
+            .remove(0);
+        COUNTRIES
+            .iter()
+            .map(|country| {
+                let template = latin_template(&countries_conf, &latin_templates, country);
+                (*country, referenced_components(template))
+            })
+            .collect()
+    };
+}
+
+/// the countries of the generated places whose template components are checked
+/// (without the countries changing the values, like GF changing its country name)
+const COUNTRIES: &[&str] = &["FR", "DE", "US", "GB", "IT", "ES", "NL", "BE", "CA", "JP"];
+
+/// the template used for a place in the latin script,
+/// following the same precedence as the formatter
+fn latin_template<'a>(
+    countries_conf: &'a Yaml,
+    latin_templates: &'a Yaml,
+    country: &str,
+) -> &'a str {
+    let country_conf = &countries_conf[country];
+    let template_country = country_conf["use_country"].as_str().unwrap_or(country);
+    let template_conf = &countries_conf[template_country];
+    template_conf["latin_address_template"]
+        .as_str()
+        .or_else(|| latin_templates[template_country].as_str())
+        .or_else(|| template_conf["address_template"].as_str())
+        .or_else(|| countries_conf["default"]["address_template"].as_str())
+        .unwrap()
+}
+
+/// the components of a template placeholders, only the first one of the `first` blocks
+/// (the other ones are not rendered when the first one is set)
+fn referenced_components(template: &str) -> Vec<Component> {
+    let first_block = Regex::new(r"\{\{#first\}\}(.*?)\{\{/first\}\}").unwrap();
+    let placeholder = Regex::new(r"\{\{\{(\w+)\}\}\}").unwrap();
+    let template = first_block.replace_all(template, |captures: &regex::Captures| {
+        captures[1].split("||").next().unwrap_or("").to_owned()
+    });
+    let mut components = placeholder
+        .captures_iter(&template)
+        .filter_map(|captures| Component::from_str(&captures[1]).ok())
+        .collect::<Vec<_>>();
+    components.dedup();
+    components
+}
+
+/// country codes of the generated places: with a template, using another country, unknown or invalid
+const COUNTRY_CODES: &[&str] = &[
+    "FR", "DE", "US", "GB", "IT", "ES", "JP", "GF", "BR", "IE", "nl", "ZZ", "FRA", "",
+];
+
+/// valid country codes: with a template or using another country
+const VALID_COUNTRY_CODES: &[&str] = &["FR", "DE", "US", "GB", "IT", "ES", "JP", "GF", "BR", "IE"];
+
+/// keys that can be given to the PlaceBuilder: components, aliases and unknown keys
+const RAW_KEYS: &[&str] = &[
+    "house_number",
+    "road",
+    "postcode",
+    "city",
+    "suburb",
+    "state",
+    "country",
+    "country_code",
+    "building",
+    "street",
+    "locality",
+    "name",
+    "brand",
+];
+
+/// raw key/values, as given to the PlaceBuilder
+type RawPlace = Vec<(&'static str, String)>;
+
+fn word() -> impl Strategy<Value = String> {
+    "[A-Z][a-z]{3,10}( [A-Z][a-z]{3,10})?"
+}
+
+fn value_for(component: Component) -> BoxedStrategy<String> {
+    match component {
+        Component::HouseNumber => "[1-9][0-9]{0,3}".boxed(),
+        Component::Postcode => "[0-9]{5}".boxed(),
+        _ => word().boxed(),
+    }
+}
+
+/// a place with all the referenced components of a country filled with distinct values
+fn referenced_place() -> impl Strategy<Value = (&'static str, Vec<(Component, String)>)> {
+    prop::sample::select(COUNTRIES_REFERENCED_COMPONENTS.clone()).prop_flat_map(
+        |(country, components)| {
+            let values: Vec<_> = components.iter().map(|c| value_for(*c)).collect();
+            (Just(country), Just(components), values)
+                .prop_filter("values must be distinct", |(_, _, values)| {
+                    values
+                        .iter()
+                        .all(|v| values.iter().filter(|o| *o == v).count() == 1)
+                })
+                .prop_map(|(country, components, values)| {
+                    (country, components.iter().cloned().zip(values).collect())
+                })
+        },
+    )
+}
+
+/// raw key/values with distinct keys, in 2 different orders
+fn shuffled_raw_place() -> impl Strategy<Value = (RawPlace, RawPlace)> {
+    prop::sample::subsequence(RAW_KEYS, 1..RAW_KEYS.len())
+        .prop_flat_map(|keys| {
+            let values: Vec<_> = keys
+                .iter()
+                .map(|k| match *k {
+                    "country_code" => prop::sample::select(vec!["fr", "de", "us", "gb", "it"])
+                        .prop_map(|s| s.to_owned())
+                        .boxed(),
+                    "house_number" => value_for(Component::HouseNumber),
+                    "postcode" => value_for(Component::Postcode),
+                    _ => word().boxed(),
+                })
+                .collect();
+            (Just(keys), values)
+        })
+        .prop_map(|(keys, values)| keys.into_iter().zip(values).collect::<Vec<_>>())
+        .prop_flat_map(|raw| (Just(raw.clone()), Just(raw).prop_shuffle()))
+}
+
+/// any place, with any component and any country code
+fn any_place() -> impl Strategy<Value = Place> {
+    (
+        prop::collection::vec(
+            (
+                prop::sample::select(Component::iter().collect::<Vec<_>>()),
+                prop_oneof![word(), "[0-9]{1,6}", ".{0,12}"],
+            ),
+            0..12,
+        ),
+        prop::option::of(prop::sample::select(COUNTRY_CODES)),
+    )
+        .prop_map(|(values, country_code)| {
+            let mut place = Place::default();
+            for (c, v) in values {
+                place[c] = Some(v);
+            }
+            if let Some(country_code) = country_code {
+                place[Component::CountryCode] = Some(country_code.to_owned());
+            }
+            place
+        })
+}
+
+proptest! {
+    #[test]
+    fn referenced_components_are_in_the_output((country, values) in referenced_place()) {
+        let mut place = Place::default();
+        place[Component::CountryCode] = Some(country.to_owned());
+        for (c, v) in &values {
+            place[*c] = Some(v.clone());
+        }
+
+        let formatted = FORMATTER.format(place).unwrap();
+
+        for (c, v) in &values {
+            prop_assert!(
+                formatted.contains(v.as_str()),
+                "{} '{}' not found in {:?} for {}", c, v, formatted, country
+            );
+        }
+    }
+
+    #[test]
+    fn output_does_not_depend_on_the_order_of_the_raw_values((raw, shuffled_raw) in shuffled_raw_place()) {
+        let place = PLACE_BUILDER.build_place(raw.into_iter());
+        let shuffled_place = PLACE_BUILDER.build_place(shuffled_raw.into_iter());
+
+        prop_assert_eq!(
+            FORMATTER.format(place).unwrap(),
+            FORMATTER.format(shuffled_place).unwrap()
+        );
+    }
+
+    #[test]
+    fn configuration_country_code_only_changes_the_template(
+        place in any_place(),
+        forced_country in prop::sample::select(VALID_COUNTRY_CODES),
+    ) {
+        let mut place_in_forced_country = place.clone();
+        place_in_forced_country[Component::CountryCode] = Some(forced_country.to_owned());
+
+        let formatted_with_config = FORMATTER.format_with_config(
+            place,
+            Configuration {
+                country_code: Some(forced_country.to_owned()),
+                ..Default::default()
+            },
+        ).unwrap();
+
+        prop_assert_eq!(
+            formatted_with_config,
+            FORMATTER.format(place_in_forced_country).unwrap()
+        );
+    }
+
+    #[test]
+    fn output_is_clean(place in any_place()) {
+        let formatted = FORMATTER.format(place).unwrap();
+
+        // a place without any usable component gives an empty output
+        if formatted.trim().is_empty() {
+            return Ok(());
+        }
+        prop_assert!(formatted.ends_with('\n'), "no final new line in {:?}", formatted);
+        for line in formatted.lines() {
+            prop_assert!(!line.trim().is_empty(), "empty line in {:?}", formatted);
+            prop_assert_eq!(line.trim(), line, "untrimmed line in {:?}", formatted);
+            prop_assert!(!line.starts_with(','), "line starting with a comma in {:?}", formatted);
+            prop_assert!(!line.ends_with(','), "line ending with a comma in {:?}", formatted);
+        }
+        for (line, next_line) in formatted.lines().zip(formatted.lines().skip(1)) {
+            prop_assert_ne!(line, next_line, "duplicated line in {:?}", formatted);
+        }
+    }
+}
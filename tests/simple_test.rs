@@ -94,6 +94,32 @@ Polynésie française
     )
 }
 
+#[test]
+fn address_builder_unknown_fields() {
+    let formatter = Formatter::default();
+    let addr_builder = address_formatter::PlaceBuilder::default();
+    let data = [
+        ("city", "Toulouse"),
+        ("country_code", "fr"),
+        ("operator", "Tisséo"),
+        ("brand", "Vélô Toulouse"),
+        ("road", "Rue du Taur"),
+    ];
+
+    // the unknown fields are in the 'Attention' field, whatever their order
+    let addr = addr_builder.build_place(data.iter().map(|(k, v)| (*k, v.to_string())));
+    let reversed_addr =
+        addr_builder.build_place(data.iter().rev().map(|(k, v)| (*k, v.to_string())));
+    assert_eq!(
+        addr[Component::Attention],
+        Some("Vélô Toulouse, Tisséo".to_owned())
+    );
+    assert_eq!(
+        formatter.format(addr).unwrap(),
+        formatter.format(reversed_addr).unwrap()
+    );
+}
+
 #[test]
 fn use_of_singleton() {
     assert_eq!(
@@ -112,3 +138,1956 @@ France
         .to_owned()
     )
 }
+
+#[test]
+fn lazy_formatter() {
+    use Component::*;
+    let lazy_formatter = Formatter::lazy();
+    let formatter = Formatter::default();
+
+    let addresses = vec![
+        hashmap!(
+            City => "Toulouse",
+            Country => "France",
+            CountryCode => "FR",
+            Postcode => "31000",
+            Road => "Rue du Médecin-Colonel Calbairac",
+        ),
+        hashmap!(
+            City => "Papeete",
+            CountryCode => "PF",
+            Postcode => "98714",
+            Road => "Rue des Remparts",
+        ),
+        hashmap!(City => "Toulouse"),
+    ];
+
+    for addr in addresses {
+        assert_eq!(
+            lazy_formatter.format(addr.clone()).unwrap(),
+            formatter.format(addr).unwrap()
+        );
+    }
+}
+
+#[test]
+fn numeric_country_replaced_by_state() {
+    use Component::*;
+    let formatter = Formatter::default();
+
+    let addr = hashmap!(
+        City => "Toulouse",
+        Country => "1",
+        CountryCode => "FR",
+        Postcode => "31000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+        State => "France",
+    );
+
+    assert_eq!(
+        formatter.format(addr).unwrap(),
+        r#"Rue du Médecin-Colonel Calbairac
+31000 Toulouse
+France
+"#
+        .to_owned()
+    )
+}
+
+#[test]
+fn components_without_words_are_ignored() {
+    use Component::*;
+    let formatter = Formatter::default();
+
+    let addr = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "-",
+        Postcode => "31000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    );
+
+    assert_eq!(
+        formatter.format(addr).unwrap(),
+        r#"Rue du Médecin-Colonel Calbairac
+31000 Toulouse
+France
+"#
+        .to_owned()
+    )
+}
+
+#[test]
+fn only_one_component_not_in_template() {
+    let formatter = Formatter::default();
+    let mut addr = Place::default();
+    addr[Component::Continent] = Some("Europe".to_owned());
+
+    assert_eq!(formatter.format(addr).unwrap(), "Europe\n".to_owned())
+}
+
+#[test]
+fn minimum_components_by_country() {
+    use address_formatter::MinimumComponents;
+    let formatter = Formatter::default();
+
+    assert_eq!(
+        formatter.minimum_components("FR"),
+        &MinimumComponents::default()
+    );
+    assert!(formatter
+        .minimum_components("IE")
+        .sets()
+        .contains(&vec![Component::House, Component::Village]));
+
+    let mut addr = Place::default();
+    addr[Component::House] = Some("Cnoc na Greine".to_owned());
+    addr[Component::Village] = Some("Ballyvourney".to_owned());
+    assert!(formatter.minimum_components("IE").is_satisfied_by(&addr));
+    assert!(!formatter.minimum_components("FR").is_satisfied_by(&addr));
+}
+
+#[test]
+fn minimum_components_without_postcodes() {
+    use Component::*;
+    let formatter = Formatter::default();
+
+    // a POI in a locality is enough in a country without postcode system
+    let (_, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "Dubai",
+                Country => "United Arab Emirates",
+                CountryCode => "AE",
+                House => "Burj Khalifa",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert!(!report.used_fallback_template());
+
+    // but a POI alone still gets the fallback template
+    let (_, report) = formatter
+        .format_with_report(
+            hashmap!(
+                Country => "United Arab Emirates",
+                CountryCode => "AE",
+                House => "Burj Khalifa",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert!(report.used_fallback_template());
+
+    // the same POI falls back in a country with postcodes
+    let (_, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                House => "Capitole",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert!(report.used_fallback_template());
+}
+
+#[test]
+fn forced_template_kind() {
+    use address_formatter::{Configuration, MinimumComponents, TemplateKind};
+    use Component::*;
+    let formatter = Formatter::default();
+
+    let addr: Place = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "17",
+        Postcode => "31000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    )
+    .into();
+    let format = |conf: Configuration| formatter.format_with_config(addr.clone(), conf).unwrap();
+
+    // the place has enough components for the main template
+    assert_eq!(
+        format(Configuration::default()),
+        format(Configuration {
+            template_kind: Some(TemplateKind::Main),
+            ..Default::default()
+        })
+    );
+    // no set of components can be satisfied, the fallback template is used
+    assert_eq!(
+        format(Configuration {
+            minimum_components: Some(MinimumComponents::new(vec![])),
+            ..Default::default()
+        }),
+        format(Configuration {
+            template_kind: Some(TemplateKind::Fallback),
+            ..Default::default()
+        })
+    );
+    // the forced template kind takes precedence over the minimum components
+    assert_eq!(
+        format(Configuration {
+            minimum_components: Some(MinimumComponents::new(vec![vec![]])),
+            template_kind: Some(TemplateKind::Fallback),
+            ..Default::default()
+        }),
+        format(Configuration {
+            template_kind: Some(TemplateKind::Fallback),
+            ..Default::default()
+        })
+    );
+}
+
+#[test]
+fn custom_cleaning_pipeline() {
+    use address_formatter::{CleaningPipeline, CleaningStep, Configuration};
+    use Component::*;
+
+    /// remove the house numbers written as words
+    struct NumericHouseNumber;
+    impl CleaningStep for NumericHouseNumber {
+        fn name(&self) -> &str {
+            "numeric_house_number"
+        }
+        fn clean(&self, addr: &mut Place) {
+            if let Some(n) = &addr[HouseNumber] {
+                if !n.chars().any(|c| c.is_ascii_digit()) {
+                    addr[HouseNumber] = None;
+                }
+            }
+        }
+    }
+
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "seventeen",
+        Postcode => "31000;31100",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    )
+    .into();
+
+    let mut cleaned = addr.clone();
+    let pipeline = CleaningPipeline::default().with(NumericHouseNumber);
+    let changes = pipeline.apply(&mut cleaned);
+    assert_eq!(
+        changes
+            .iter()
+            .map(|c| (c.step.as_str(), c.component))
+            .collect::<Vec<_>>(),
+        vec![
+            ("postcode_list", Postcode),
+            ("numeric_house_number", HouseNumber)
+        ]
+    );
+    assert!(changes.iter().all(|c| c.is_removal()));
+
+    assert_eq!(
+        formatter
+            .format_with_config(
+                addr.clone(),
+                Configuration {
+                    cleaning_pipeline: Some(pipeline),
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+        r#"Rue du Médecin-Colonel Calbairac
+Toulouse
+France
+"#
+        .to_owned()
+    );
+
+    // without the postcode_list step, the postcode is kept
+    let formatted = formatter
+        .format_with_config(
+            addr,
+            Configuration {
+                cleaning_pipeline: Some(CleaningPipeline::default().without("postcode_list")),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(formatted.contains("31000;31100"));
+}
+
+#[test]
+fn format_report() {
+    use Component::*;
+    let formatter = Formatter::default();
+
+    let (text, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                House => "Place du Capitole",
+                Postcode => "31000",
+                Road => "Place du Capitole",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        text,
+        r#"Place du Capitole
+31000 Toulouse
+France
+"#
+    );
+    assert_eq!(report.country_code, Some("FR".to_owned()));
+    assert!(!report.used_fallback_template());
+    assert!(report.cleaned.is_empty());
+    assert!(report.unused_components.is_empty());
+    assert_eq!(
+        report.collapsed_duplicates,
+        vec!["Place du Capitole".to_owned()]
+    );
+    assert!(!report.has_data_loss());
+
+    let (_, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                HouseNumber => "-",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert!(report.used_fallback_template());
+    assert_eq!(report.cleaned.len(), 1);
+    assert_eq!(report.cleaned[0].component, HouseNumber);
+    assert!(report.has_data_loss());
+
+    // only the city is rendered by the template, the village is lost
+    // even if its value is part of another component
+    let (text, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "Blagnac",
+                Country => "France",
+                CountryCode => "FR",
+                Postcode => "31700",
+                Road => "Route de Toulouse",
+                Village => "Toulouse",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert!(text.contains("Toulouse"));
+    assert_eq!(report.unused_components, vec![Village]);
+    assert!(report.has_data_loss());
+}
+
+#[test]
+fn localized_overridden_territory() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+
+    // French Guiana uses the configuration of France, but keeps its own name
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Cayenne",
+                Country => "Guyane",
+                CountryCode => "GF",
+                Postcode => "97300",
+                Road => "Rue de Rémire",
+            ),
+            Configuration {
+                output_language: Some("en".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("French Guiana"));
+
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Saint-Denis",
+                Country => "Réunion",
+                CountryCode => "RE",
+                Postcode => "97400",
+                Road => "Rue de Paris",
+            ),
+            Configuration {
+                output_language: Some("fr".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("La Réunion"));
+
+    // Puerto Rico uses the configuration of the United States
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "San Juan",
+                Country => "Puerto Rico",
+                CountryCode => "PR",
+                Postcode => "00901",
+                Road => "Calle Fortaleza",
+            ),
+            Configuration {
+                output_language: Some("fr".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("Porto Rico"));
+}
+
+#[test]
+fn valid_postcodes_are_normalized() {
+    use address_formatter::normalize_postcode;
+    let cases = vec![
+        ("GB", "sw1a1aa", "SW1A 1AA"),
+        ("GB", "M1 1AE", "M1 1AE"),
+        ("UK", "ec1a 1bb", "EC1A 1BB"),
+        ("NL", "1234ab", "1234 AB"),
+        ("CA", "k1a0b1", "K1A 0B1"),
+        ("BR", "01310100", "01310-100"),
+        ("BR", "01310-100", "01310-100"),
+        ("US", "10001", "10001"),
+        ("US", "100011234", "10001-1234"),
+        ("IE", "a65f4e2", "A65 F4E2"),
+        ("JP", "1000001", "100-0001"),
+        ("PL", "00 950", "00-950"),
+        ("SE", "11455", "114 55"),
+        ("FR", " 31000 ", "31000"),
+    ];
+    for (country, postcode, expected) in cases {
+        assert_eq!(
+            normalize_postcode(country, postcode).unwrap(),
+            expected,
+            "for {} in {}",
+            postcode,
+            country
+        );
+    }
+}
+
+#[test]
+fn invalid_postcodes() {
+    use address_formatter::normalize_postcode;
+    let cases = vec![
+        ("GB", "SW1A"),
+        ("NL", "0123 AB"),
+        ("CA", "D1A 0B1"),
+        ("US", "1000"),
+        ("FR", "310000"),
+        ("DE", "abcde"),
+    ];
+    for (country, postcode) in cases {
+        assert!(
+            normalize_postcode(country, postcode).is_err(),
+            "{} should not be valid in {}",
+            postcode,
+            country
+        );
+    }
+}
+
+#[test]
+fn unknown_countries_are_not_validated() {
+    use address_formatter::normalize_postcode;
+    assert_eq!(normalize_postcode("AQ", " BIQQ 1ZZ ").unwrap(), "BIQQ 1ZZ");
+    assert!(normalize_postcode("not a country", "31000").is_err());
+}
+
+#[test]
+fn normalized_postcode_in_formatted_place() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        Postcode => "31 000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    );
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                normalize_postcodes: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        text,
+        r#"Rue du Médecin-Colonel Calbairac
+31000 Toulouse
+France
+"#
+    );
+    assert_eq!(report.invalid_postcode, None);
+    assert_eq!(report.cleaned[0].new_value, Some("31000".to_owned()));
+
+    // the postcodes are not normalized by default
+    assert!(formatter.format(addr).unwrap().contains("31 000"));
+}
+
+#[test]
+fn invalid_postcode_in_formatted_place() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        Postcode => "310",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    );
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                normalize_postcodes: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(text.contains("310 Toulouse"));
+    assert_eq!(report.invalid_postcode, Some("310".to_owned()));
+    assert!(report.cleaned.is_empty());
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr,
+            Configuration {
+                normalize_postcodes: Some(true),
+                drop_invalid_postcodes: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!text.contains("310"));
+    assert_eq!(report.invalid_postcode, Some("310".to_owned()));
+    assert!(report.has_data_loss());
+}
+
+#[test]
+fn postcode_of_country_without_postcodes() {
+    use address_formatter::{uses_postcodes, Configuration};
+    use Component::*;
+    assert!(!uses_postcodes("HK"));
+    assert!(uses_postcodes("GB"));
+
+    let formatter = Formatter::default();
+    let addr = hashmap!(
+        City => "Hong Kong",
+        Country => "Hong Kong",
+        CountryCode => "HK",
+        Postcode => "999077",
+        Road => "Queen's Road Central",
+        HouseNumber => "1",
+    );
+
+    // by default, the postcode is only reported
+    let (text, report) = formatter
+        .format_with_report(addr.clone(), Default::default())
+        .unwrap();
+    assert!(text.contains("999077"));
+    assert_eq!(report.invalid_postcode, Some("999077".to_owned()));
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr,
+            Configuration {
+                drop_postcodes_without_postal_system: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!text.contains("999077"));
+    assert_eq!(report.invalid_postcode, Some("999077".to_owned()));
+    assert_eq!(report.cleaned[0].step, "no_postcode_system");
+}
+
+#[test]
+fn multi_value_policies() {
+    use address_formatter::{CleaningPipeline, MultiValuePolicy, MultiValues};
+    use Component::*;
+
+    let clean = |policy: MultiValuePolicy, component: Component, value: &str, country: &str| {
+        let mut place = Place::default();
+        place[component] = Some(value.to_owned());
+        place[CountryCode] = Some(country.to_owned());
+        CleaningPipeline::empty()
+            .with(MultiValues(policy))
+            .apply(&mut place);
+        place[component].clone().unwrap_or_default()
+    };
+
+    let policy = MultiValuePolicy::KeepFirst;
+    assert_eq!(clean(policy.clone(), HouseNumber, "12;14", "FR"), "12");
+    assert_eq!(clean(policy.clone(), HouseNumber, "12-14", "FR"), "12");
+    assert_eq!(
+        clean(policy.clone(), Road, "Main Street;Second Street", "GB"),
+        "Main Street"
+    );
+    assert_eq!(
+        clean(policy.clone(), Postcode, "75001;75002", "FR"),
+        "75001"
+    );
+    assert_eq!(clean(policy.clone(), HouseNumber, "12;12", "FR"), "12");
+    // single values are kept
+    assert_eq!(clean(policy.clone(), HouseNumber, "12", "FR"), "12");
+    // not a range
+    assert_eq!(clean(policy.clone(), HouseNumber, "14-12", "FR"), "14-12");
+    // the dash is part of the house number in those countries
+    assert_eq!(clean(policy.clone(), HouseNumber, "1-2", "JP"), "1-2");
+    assert_eq!(clean(policy, HouseNumber, "37-42", "US"), "37-42");
+
+    assert_eq!(
+        clean(MultiValuePolicy::Join, HouseNumber, "12; 14", "FR"),
+        "12, 14"
+    );
+    assert_eq!(
+        clean(
+            MultiValuePolicy::Join,
+            Road,
+            "Main Street;Second Street",
+            "GB"
+        ),
+        "Main Street, Second Street"
+    );
+    assert_eq!(
+        clean(MultiValuePolicy::Join, HouseNumber, "12;14", "JP"),
+        "12、14"
+    );
+    assert_eq!(
+        clean(
+            MultiValuePolicy::JoinWith(" & ".to_owned()),
+            HouseNumber,
+            "12;14",
+            "GB"
+        ),
+        "12 & 14"
+    );
+
+    let policy = MultiValuePolicy::Range;
+    assert_eq!(clean(policy.clone(), HouseNumber, "12;14", "FR"), "12–14");
+    assert_eq!(
+        clean(policy.clone(), HouseNumber, "14;10;12", "FR"),
+        "10–14"
+    );
+    assert_eq!(clean(policy.clone(), HouseNumber, "12 - 14", "DE"), "12–14");
+    assert_eq!(
+        clean(policy.clone(), Postcode, "01000;01001", "FR"),
+        "01000–01001"
+    );
+    // the values with a gap are not a range
+    assert_eq!(clean(policy.clone(), HouseNumber, "12;20", "FR"), "12, 20");
+    assert_eq!(
+        clean(policy.clone(), HouseNumber, "12;13;20", "FR"),
+        "12, 13, 20"
+    );
+    assert_eq!(
+        clean(policy.clone(), Postcode, "01000;01100", "FR"),
+        "01000, 01100"
+    );
+    // but the values written as a range are kept as a range
+    assert_eq!(clean(policy.clone(), HouseNumber, "12-20", "FR"), "12–20");
+    // non numeric values are joined
+    assert_eq!(
+        clean(policy.clone(), HouseNumber, "12a;14", "FR"),
+        "12a, 14"
+    );
+    assert_eq!(
+        clean(policy, Road, "Main Street;Second Street", "GB"),
+        "Main Street, Second Street"
+    );
+}
+
+#[test]
+fn multi_values_in_formatted_place() {
+    use address_formatter::{Configuration, MultiValuePolicy};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "17;19",
+        Postcode => "31000;31100",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    );
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                multi_value_policy: Some(MultiValuePolicy::KeepFirst),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        text,
+        r#"17 Rue du Médecin-Colonel Calbairac
+31000 Toulouse
+France
+"#
+    );
+    assert_eq!(report.cleaned.len(), 2);
+    assert!(report.cleaned.iter().all(|c| c.step == "multi_values"));
+
+    // by default the multiple values are kept, but the list of postcodes is removed
+    assert_eq!(
+        formatter.format(addr).unwrap(),
+        r#"17;19 Rue du Médecin-Colonel Calbairac
+Toulouse
+France
+"#
+    );
+}
+
+#[test]
+fn french_postal_label() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Saint-Étienne",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "2",
+        Postcode => "42000",
+        Road => "Place de l'Hôtel de Ville",
+    )
+    .into();
+
+    assert_eq!(
+        formatter
+            .format_with_config(
+                addr.clone(),
+                Configuration {
+                    postal_label: Some(true),
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+        r#"2 Place de l'Hôtel de Ville
+42000 SAINT-ETIENNE
+FRANCE
+"#
+    );
+    // the postal rules are only applied on demand
+    assert!(formatter
+        .format(addr)
+        .unwrap()
+        .contains("42000 Saint-Étienne"));
+}
+
+#[test]
+fn lines_roles() {
+    use address_formatter::LineRole;
+    use Component::*;
+    let formatter = Formatter::default();
+    let lines = formatter
+        .format_lines(
+            hashmap!(
+                Attention => "Mairie",
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                HouseNumber => "1",
+                Postcode => "31000",
+                Road => "Place du Capitole",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+
+    assert_eq!(
+        lines
+            .iter()
+            .map(|l| (l.text.as_str(), l.role))
+            .collect::<Vec<_>>(),
+        vec![
+            ("Mairie", LineRole::Recipient),
+            ("1 Place du Capitole", LineRole::Street),
+            ("31000 Toulouse", LineRole::Locality),
+            ("France", LineRole::Country),
+        ]
+    );
+    // the house number is not found in the postcode
+    assert_eq!(lines[1].components, vec![HouseNumber, Road]);
+    assert_eq!(lines[2].components, vec![City, Postcode]);
+}
+
+#[test]
+fn lines_roles_with_names_in_the_road() {
+    use address_formatter::{Configuration, LineRole};
+    use Component::*;
+    let formatter = Formatter::default();
+    let roles = |lines: &[address_formatter::FormattedLine]| {
+        lines.iter().map(|l| l.role).collect::<Vec<_>>()
+    };
+
+    let lines = formatter
+        .format_lines(
+            hashmap!(
+                City => "Paris",
+                Country => "France",
+                CountryCode => "FR",
+                HouseNumber => "12",
+                Postcode => "75016",
+                Road => "Avenue de Paris",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(lines[0].text, "12 Avenue de Paris");
+    assert_eq!(lines[0].components, vec![HouseNumber, Road]);
+    assert_eq!(
+        roles(&lines),
+        vec![LineRole::Street, LineRole::Locality, LineRole::Country]
+    );
+
+    let lines = formatter
+        .format_lines(
+            hashmap!(
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                Postcode => "31000",
+                Road => "Rue de France",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(lines[0].text, "Rue de France");
+    assert_eq!(lines[0].components, vec![Road]);
+    assert_eq!(
+        roles(&lines),
+        vec![LineRole::Street, LineRole::Locality, LineRole::Country]
+    );
+
+    // the country of an international mail is moved to the last line
+    let lines = formatter
+        .format_lines(
+            hashmap!(
+                Attention => "France Télévisions",
+                City => "Paris",
+                Country => "France",
+                CountryCode => "FR",
+                HouseNumber => "7",
+                Postcode => "75015",
+                Road => "Esplanade Henri de France",
+            ),
+            Configuration {
+                origin_country: Some("DE".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        roles(&lines),
+        vec![
+            LineRole::Recipient,
+            LineRole::Street,
+            LineRole::Locality,
+            LineRole::Country
+        ]
+    );
+}
+
+#[test]
+fn us_postal_label() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let lines = formatter
+        .format_lines(
+            hashmap!(
+                City => "St. Louis",
+                Country => "United States of America",
+                CountryCode => "US",
+                HouseNumber => "1",
+                Postcode => "63101",
+                Road => "S. Memorial Dr.",
+                State => "Missouri",
+            ),
+            Configuration {
+                postal_label: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    for line in &lines {
+        assert_eq!(line.text, line.text.to_uppercase());
+        assert!(!line.text.contains('.'), "{} has punctuation", line.text);
+    }
+    assert_eq!(lines[0].text, "1 S MEMORIAL DR");
+}
+
+#[test]
+fn no_rules_for_the_country() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Berlin",
+        Country => "Deutschland",
+        CountryCode => "DE",
+        HouseNumber => "1",
+        Postcode => "10117",
+        Road => "Unter den Linden",
+    )
+    .into();
+
+    assert_eq!(
+        formatter
+            .format_with_config(
+                addr.clone(),
+                Configuration {
+                    postal_label: Some(true),
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+        formatter.format(addr).unwrap()
+    );
+}
+
+#[test]
+fn label() {
+    use address_formatter::LabelSize;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        Attention => "Mairie de Toulouse",
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "1",
+        Postcode => "31000",
+        Road => "Place du Capitole",
+        Suburb => "Capitole",
+    )
+    .into();
+    let text = formatter.format(addr.clone()).unwrap();
+
+    // the place fits the label
+    let label = formatter
+        .format_label(addr.clone(), Default::default(), LabelSize::default())
+        .unwrap();
+    assert!(label.fits);
+    assert!(!label.abbreviated);
+    assert!(!label.wrapped);
+    assert!(label.removed_components.is_empty());
+    assert_eq!(format!("{}\n", label.lines.join("\n")), text);
+
+    // the long lines are wrapped
+    let label = formatter
+        .format_label(
+            addr.clone(),
+            Default::default(),
+            LabelSize {
+                max_width: 12,
+                max_lines: 8,
+            },
+        )
+        .unwrap();
+    assert!(label.fits);
+    assert!(label.wrapped);
+    assert!(label.lines.iter().all(|l| l.chars().count() <= 12));
+    assert!(label.lines.contains(&"Toulouse".to_owned()));
+    assert!(label.removed_components.is_empty());
+
+    // the least important components are removed
+    let label = formatter
+        .format_label(
+            addr.clone(),
+            Default::default(),
+            LabelSize {
+                max_width: 35,
+                max_lines: text.lines().count() - 1,
+            },
+        )
+        .unwrap();
+    assert!(label.fits);
+    assert_eq!(label.removed_components, vec![Attention]);
+    assert!(label.lines.iter().all(|l| !l.contains("Mairie")));
+
+    // the place cannot fit the label
+    let label = formatter
+        .format_label(
+            addr,
+            Default::default(),
+            LabelSize {
+                max_width: 5,
+                max_lines: 2,
+            },
+        )
+        .unwrap();
+    assert!(!label.fits);
+    assert!(!label.lines.is_empty());
+}
+
+#[test]
+fn abbreviated_label() {
+    use address_formatter::{Configuration, LabelSize};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        Attention => "Mairie de Toulouse",
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "1",
+        Postcode => "31000",
+        Road => "Avenue de la Gloire",
+    )
+    .into();
+
+    let abbreviated = formatter
+        .format_with_config(
+            addr.clone(),
+            Configuration {
+                abbreviate: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!abbreviated.contains("Avenue"));
+
+    let label = formatter
+        .format_label(
+            addr.clone(),
+            Default::default(),
+            LabelSize {
+                max_width: 18,
+                max_lines: 6,
+            },
+        )
+        .unwrap();
+    assert!(label.fits);
+    assert!(label.abbreviated);
+    assert!(label.lines.iter().all(|l| !l.contains("Avenue")));
+
+    // the abbreviations can be disabled
+    let label = formatter
+        .format_label(
+            addr,
+            Configuration {
+                abbreviate: Some(false),
+                ..Default::default()
+            },
+            LabelSize {
+                max_width: 18,
+                max_lines: 6,
+            },
+        )
+        .unwrap();
+    assert!(!label.abbreviated);
+    assert!(label.wrapped);
+    assert!(label.lines.iter().any(|l| l.contains("Avenue")));
+}
+
+#[test]
+fn origin_country() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "17",
+        Postcode => "31000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    )
+    .into();
+
+    // domestic mail
+    let text = formatter
+        .format_with_config(
+            addr.clone(),
+            Configuration {
+                origin_country: Some("FR".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!text.contains("France"));
+    assert!(!text.contains("FRANCE"));
+
+    // international mail
+    let text = formatter
+        .format_with_config(
+            addr,
+            Configuration {
+                origin_country: Some("DE".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("FRANCE"));
+    assert!(!text.contains("France"));
+
+    // the country is written in the language of the origin country
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Berlin",
+                Country => "Deutschland",
+                CountryCode => "DE",
+                HouseNumber => "1",
+                Postcode => "10117",
+                Road => "Unter den Linden",
+            ),
+            Configuration {
+                origin_country: Some("FR".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("ALLEMAGNE"));
+
+    // a french overseas department uses the template of France,
+    // but its country is compared with the origin before the overrides
+    let guyane: Place = hashmap!(
+        City => "Cayenne",
+        Country => "Guyane",
+        CountryCode => "GF",
+        Postcode => "97300",
+        Road => "Rue de Rémire",
+    )
+    .into();
+    let send_from = |origin: &str| {
+        formatter
+            .format_with_config(
+                guyane.clone(),
+                Configuration {
+                    origin_country: Some(origin.to_owned()),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+    assert_eq!(send_from("FR").lines().last(), Some("GUYANE FRANÇAISE"));
+    assert_eq!(send_from("DE").lines().last(), Some("FRENCH GUIANA"));
+    assert_eq!(send_from("GF").lines().last(), Some("97300 Cayenne"));
+}
+
+#[test]
+fn output_language() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "17",
+        Postcode => "31000",
+        Road => "Rue du Médecin-Colonel Calbairac",
+    )
+    .into();
+    let format = |addr: Place, language: &str| {
+        formatter
+            .format_with_config(
+                addr,
+                Configuration {
+                    output_language: Some(language.to_owned()),
+                    ..Default::default()
+                },
+            )
+            .unwrap()
+    };
+
+    assert_eq!(
+        format(addr.clone(), "de").lines().last(),
+        Some("Frankreich")
+    );
+    assert_eq!(format(addr.clone(), "ja").lines().last(), Some("フランス"));
+    // an unknown language keeps the country
+    assert_eq!(format(addr.clone(), "tlh").lines().last(), Some("France"));
+
+    // the country is not added
+    let mut without_country = addr.clone();
+    without_country[Country] = None;
+    assert!(!format(without_country, "de").contains("Frankreich"));
+
+    // international mail in the output language
+    let text = formatter
+        .format_with_config(
+            addr,
+            Configuration {
+                origin_country: Some("DE".to_owned()),
+                output_language: Some("de".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("FRANKREICH"));
+
+    // the standard names are used, without abbreviations
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Gustavia",
+                Country => "Saint-Barthélemy",
+                CountryCode => "BL",
+                Postcode => "97133",
+                Road => "Rue du Bord de Mer",
+            ),
+            Configuration {
+                origin_country: Some("US".to_owned()),
+                output_language: Some("en".to_owned()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(text.lines().last(), Some("SAINT BARTHÉLEMY"));
+}
+
+#[test]
+fn localized_state() {
+    use address_formatter::{Configuration, RegionNames};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "千代田区",
+        Country => "日本",
+        CountryCode => "JP",
+        Postcode => "100-0001",
+        Road => "千代田",
+        State => "東京都",
+    )
+    .into();
+    let conf = Configuration {
+        region_names: Some(
+            RegionNames::empty()
+                .with("JP-13", "ja", "東京都")
+                .with("JP-13", "en", "Tokyo"),
+        ),
+        output_language: Some("en".to_owned()),
+        ..Default::default()
+    };
+    let text = formatter.format_with_config(addr.clone(), conf).unwrap();
+    assert!(text.contains("Tokyo"));
+    assert!(text.contains("Japan"));
+
+    // the state code is used first
+    let mut addr = addr;
+    addr[StateCode] = Some("13".to_owned());
+    addr[State] = Some("Tôkyô".to_owned());
+    let conf = Configuration {
+        region_names: Some(RegionNames::empty().with("JP-13", "en", "Tokyo")),
+        output_language: Some("en".to_owned()),
+        ..Default::default()
+    };
+    let text = formatter.format_with_config(addr, conf).unwrap();
+    assert!(text.contains("Tokyo"));
+}
+
+#[test]
+fn region_names_table() {
+    use address_formatter::RegionNames;
+    let names = RegionNames::default();
+    assert_eq!(names.name("DE-BY", "en"), Some("Bavaria"));
+    assert_eq!(names.find_code("de", "Bayern"), Some("DE-BY"));
+    assert_eq!(names.name("BE-VAN", "fr"), Some("Anvers"));
+
+    let names = RegionNames::from_yaml("BE-WLG: {fr: Liège, nl: Luik}").unwrap();
+    assert_eq!(names.name("BE-WLG", "nl-BE"), Some("Luik"));
+    assert_eq!(names.find_code("BE", "LIÈGE"), Some("BE-WLG"));
+    assert_eq!(names.name("DE-BY", "en"), None);
+    assert!(RegionNames::from_yaml("- not a table").is_err());
+}
+
+#[test]
+#[cfg(feature = "transliteration")]
+fn transliterated_japanese_place() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "千代田区",
+        Country => "日本",
+        CountryCode => "JP",
+        HouseNumber => "1",
+        Postcode => "100-0001",
+        Road => "千代田",
+        State => "東京都",
+    )
+    .into();
+
+    let (text, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                transliterate: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let lines = text.lines().collect::<Vec<_>>();
+    // the latin template starts with the house number and ends with the country
+    // the kanji are kept, their japanese reading is not known
+    assert_eq!(lines.first(), Some(&"1 千代田"));
+    assert_eq!(lines.last(), Some(&"Japan"));
+    assert!(report.untransliterated);
+
+    let text = formatter.format(addr).unwrap();
+    assert_eq!(text.lines().next(), Some("日本"));
+
+    // the chinese characters of the other countries are transliterated
+    let (text, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "北京市",
+                Country => "中国",
+                CountryCode => "CN",
+                Postcode => "100000",
+            ),
+            Configuration {
+                transliterate: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(text.contains("Bei Jing Shi"));
+    assert!(text.is_ascii());
+    assert!(!report.untransliterated);
+}
+
+#[test]
+#[cfg(feature = "transliteration")]
+fn transliterated_korean_place() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "서울",
+                Country => "대한민국",
+                CountryCode => "KR",
+                Postcode => "04524",
+            ),
+            Configuration {
+                transliterate: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(text.contains("Seoul"), "{}", text);
+    assert_eq!(text.lines().last(), Some("South Korea"));
+}
+
+#[test]
+#[cfg(feature = "transliteration")]
+fn transliterated_lines() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Москва",
+        Country => "Россия",
+        CountryCode => "RU",
+        HouseNumber => "1",
+        Postcode => "101000",
+        Road => "Тверская улица",
+    )
+    .into();
+
+    let lines = formatter
+        .format_lines(
+            addr,
+            Configuration {
+                transliterate: Some(true),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(lines[0].text, "1 Tverskaia ulitsa");
+    assert_eq!(lines[0].components, vec![HouseNumber, Road]);
+    assert_eq!(lines[1].text, "101000 Moskva");
+    assert_eq!(lines[2].text, "Russia");
+}
+
+#[test]
+#[cfg(feature = "transliteration")]
+fn transliteration_in_output_language() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Αθήνα",
+        Country => "Ελλάδα",
+        CountryCode => "GR",
+        Postcode => "105 57",
+        Road => "Ερμού",
+    )
+    .into();
+    let conf = Configuration {
+        output_language: Some("fr".to_owned()),
+        transliterate: Some(true),
+        ..Default::default()
+    };
+    let text = formatter.format_with_config(addr, conf).unwrap();
+    assert_eq!(text.lines().last(), Some("Grèce"));
+    assert!(text.contains("Athena"));
+}
+
+#[test]
+fn template_script() {
+    use address_formatter::{Configuration, TemplateScript};
+    use Component::*;
+    let formatter = Formatter::default();
+    let romanized: Place = hashmap!(
+        City => "Chiyoda",
+        Country => "Japan",
+        CountryCode => "JP",
+        HouseNumber => "1",
+        Postcode => "100-0001",
+        Road => "Chiyoda",
+        State => "Tōkyō",
+    )
+    .into();
+
+    // the latin template is used for a place in the latin script
+    let (text, report) = formatter
+        .format_with_report(romanized.clone(), Default::default())
+        .unwrap();
+    assert_eq!(report.template_script, TemplateScript::Latin);
+    let lines = text.lines().collect::<Vec<_>>();
+    assert_eq!(lines.first(), Some(&"1 Chiyoda"));
+    assert_eq!(lines.last(), Some(&"Japan"));
+
+    // the template script can be forced
+    let (text, report) = formatter
+        .format_with_report(
+            romanized,
+            Configuration {
+                template_script: Some(TemplateScript::Native),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.template_script, TemplateScript::Native);
+    assert_eq!(text.lines().next(), Some("Japan"));
+
+    // the native template is used for a place in the native script
+    let (text, report) = formatter
+        .format_with_report(
+            hashmap!(
+                City => "千代田区",
+                Country => "日本",
+                CountryCode => "JP",
+                HouseNumber => "1",
+                Postcode => "100-0001",
+                Road => "千代田",
+                State => "東京都",
+            ),
+            Default::default(),
+        )
+        .unwrap();
+    assert_eq!(report.template_script, TemplateScript::Native);
+    assert_eq!(text.lines().next(), Some("日本"));
+}
+
+#[test]
+fn no_latin_template() {
+    use address_formatter::{Configuration, TemplateScript};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Toulouse",
+        Country => "France",
+        CountryCode => "FR",
+        Postcode => "31000",
+        Road => "Rue du Taur",
+    )
+    .into();
+
+    // the countries without latin template use their main template
+    assert_eq!(
+        formatter
+            .format_with_config(
+                addr.clone(),
+                Configuration {
+                    template_script: Some(TemplateScript::Latin),
+                    ..Default::default()
+                }
+            )
+            .unwrap(),
+        formatter.format(addr.clone()).unwrap()
+    );
+    let (_, report) = formatter
+        .format_with_report(addr, Default::default())
+        .unwrap();
+    assert_eq!(report.template_script, TemplateScript::Native);
+}
+
+#[test]
+fn isolated_bidi_components() {
+    use address_formatter::{Configuration, TextDirection};
+    use Component::*;
+    let formatter = Formatter::default();
+    let isolated = Configuration {
+        isolate_bidi: Some(true),
+        ..Default::default()
+    };
+    let addr: Place = hashmap!(
+        City => "القاهرة",
+        Country => "مصر",
+        CountryCode => "EG",
+        House => "City Stars",
+        HouseNumber => "12",
+        Postcode => "11511",
+        Road => "شارع عمر بن الخطاب",
+    )
+    .into();
+
+    let text = formatter
+        .format_with_config(addr.clone(), isolated.clone())
+        .unwrap();
+    assert!(text.contains("\u{2068}City Stars\u{2069}"));
+    assert!(text.contains("\u{2068}12\u{2069}"));
+    assert!(text.contains("\u{2068}11511\u{2069}"));
+    // the components in the direction of the country are not isolated
+    assert!(text.contains("\nمصر"));
+    assert!(!text.contains("\u{2068}شارع"));
+
+    let text = formatter.format(addr.clone()).unwrap();
+    assert!(!text.contains('\u{2068}'));
+
+    let lines = formatter
+        .format_lines(addr.clone(), isolated.clone())
+        .unwrap();
+    let house = lines
+        .iter()
+        .find(|l| l.components.contains(&House))
+        .unwrap();
+    assert_eq!(house.direction, TextDirection::LeftToRight);
+    let country = lines
+        .iter()
+        .find(|l| l.components.contains(&Country))
+        .unwrap();
+    assert_eq!(country.direction, TextDirection::RightToLeft);
+    // the lines without letters have the direction of the country
+    let mut addr = addr;
+    addr[City] = None;
+    addr[Road] = None;
+    let lines = formatter.format_lines(addr, isolated.clone()).unwrap();
+    let postcode = lines
+        .iter()
+        .find(|l| l.components.contains(&Postcode))
+        .unwrap();
+    assert_eq!(postcode.direction, TextDirection::RightToLeft);
+
+    // a right to left component in a left to right place
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Paris",
+                Country => "France",
+                CountryCode => "FR",
+                House => "מסעדת שלום",
+                HouseNumber => "12",
+                Postcode => "75004",
+                Road => "Rue des Rosiers",
+            ),
+            isolated,
+        )
+        .unwrap();
+    assert!(text.contains("\u{2068}מסעדת שלום\u{2069}"));
+    assert!(text.contains("12 Rue des Rosiers"));
+}
+
+#[test]
+fn normalized_input() {
+    use address_formatter::{Configuration, UnicodeNormalization};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Saint-E\u{301}tienne",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "2",
+        Postcode => "42000",
+        Road => "Place\u{a0}de  l'Hôtel\u{200b} de Ville",
+        Town => "Saint-Étienne",
+    )
+    .into();
+
+    let (text, report) = formatter
+        .format_with_report(addr.clone(), Default::default())
+        .unwrap();
+    assert_eq!(
+        text,
+        r#"2 Place de l'Hôtel de Ville
+42000 Saint-Étienne
+France
+"#
+    );
+    let normalized = report
+        .cleaned
+        .iter()
+        .filter(|c| c.step == UnicodeNormalization::NAME)
+        .map(|c| c.component)
+        .collect::<Vec<_>>();
+    assert_eq!(normalized, vec![Road, City]);
+    assert!(!report.has_data_loss());
+
+    // the raw input is kept without normalization
+    let (text, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                normalize_input: Some(false),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(text.contains("Saint-E\u{301}tienne"));
+    assert!(text.contains('\u{200b}'));
+    assert!(report.cleaned.is_empty());
+
+    // the invisible values are removed
+    let mut addr = addr;
+    addr[House] = Some("\u{200b}\u{feff}".to_owned());
+    let (_, report) = formatter
+        .format_with_report(addr, Default::default())
+        .unwrap();
+    let house = report
+        .cleaned
+        .iter()
+        .find(|c| c.component == House)
+        .unwrap();
+    assert!(house.is_removal());
+}
+
+#[test]
+fn duplicated_areas() {
+    use address_formatter::{CleaningPipeline, FuzzyDuplicates};
+    use Component::*;
+    let mut place: Place = hashmap!(
+        City => "Toulouse",
+        County => "TOULOUSE",
+        State => "Occitanie",
+        StateDistrict => "Département de la Haute-Garonne",
+    )
+    .into();
+    let changes = CleaningPipeline::empty()
+        .with(FuzzyDuplicates::default())
+        .apply(&mut place);
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].step, FuzzyDuplicates::NAME);
+    assert_eq!(changes[0].component, County);
+    assert!(changes[0].is_removal());
+    assert_eq!(place[State], Some("Occitanie".to_owned()));
+
+    let mut place: Place = hashmap!(
+        City => "Berlin",
+        State => "Land Berlin",
+    )
+    .into();
+    CleaningPipeline::empty()
+        .with(FuzzyDuplicates::default())
+        .apply(&mut place);
+    assert_eq!(place[State], None);
+
+    let mut place: Place = hashmap!(
+        City => "Saint-Étienne",
+        County => "saint-etienne",
+        Suburb => "Centre",
+    )
+    .into();
+    CleaningPipeline::empty()
+        .with(FuzzyDuplicates::default())
+        .apply(&mut place);
+    assert_eq!(place[County], None);
+    assert_eq!(place[Suburb], Some("Centre".to_owned()));
+}
+
+#[test]
+fn contained_areas() {
+    use address_formatter::{CleaningPipeline, Configuration, FuzzyDuplicates};
+    use Component::*;
+    let formatter = Formatter::default();
+    let addr: Place = hashmap!(
+        City => "Paris",
+        CityDistrict => "Paris 1er Arrondissement",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "1",
+        Postcode => "75001",
+        Road => "Rue de Rivoli",
+    )
+    .into();
+
+    // the containment is disabled by default
+    let (_, report) = formatter
+        .format_with_report(
+            addr.clone(),
+            Configuration {
+                cleaning_pipeline: Some(
+                    CleaningPipeline::default().with(FuzzyDuplicates::default()),
+                ),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(report.cleaned.is_empty());
+
+    let with_containment = FuzzyDuplicates {
+        containment: true,
+        ..Default::default()
+    };
+    let (_, report) = formatter
+        .format_with_report(
+            addr,
+            Configuration {
+                cleaning_pipeline: Some(CleaningPipeline::default().with(with_containment)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(report
+        .cleaned
+        .iter()
+        .any(|c| c.step == FuzzyDuplicates::NAME && c.component == CityDistrict));
+}
+
+#[test]
+fn distinct_areas_sharing_words() {
+    use address_formatter::{CleaningPipeline, FuzzyDuplicates};
+    use Component::*;
+
+    let places: Vec<Place> = vec![
+        hashmap!(
+            City => "York",
+            County => "York County",
+            State => "Pennsylvania",
+        )
+        .into(),
+        hashmap!(
+            City => "New York",
+            County => "York County",
+        )
+        .into(),
+        hashmap!(
+            City => "York",
+            State => "New York",
+        )
+        .into(),
+        hashmap!(
+            City => "Iowa City",
+            State => "Iowa",
+        )
+        .into(),
+        hashmap!(
+            City => "Kansas City",
+            State => "Kansas",
+        )
+        .into(),
+    ];
+    for place in places {
+        let mut cleaned = place.clone();
+        let changes = CleaningPipeline::empty()
+            .with(FuzzyDuplicates::default())
+            .apply(&mut cleaned);
+        assert!(changes.is_empty(), "{:?} changed {:?}", changes, place);
+    }
+}
+
+#[test]
+fn title_case() {
+    use address_formatter::Configuration;
+    use Component::*;
+    let formatter = Formatter::default();
+    let title_cased = Configuration {
+        title_case: Some(true),
+        ..Default::default()
+    };
+
+    let addr: Place = hashmap!(
+        City => "SAINT-JEAN-DE-LUZ",
+        Country => "France",
+        CountryCode => "FR",
+        HouseNumber => "12",
+        Postcode => "64500",
+        Road => "RUE DE L'ÉGLISE",
+    )
+    .into();
+    assert_eq!(
+        formatter
+            .format_with_config(addr.clone(), title_cased.clone())
+            .unwrap(),
+        r#"12 Rue de l'Église
+64500 Saint-Jean-de-Luz
+France
+"#
+    );
+    // the title casing is optional
+    assert!(formatter.format(addr).unwrap().contains("RUE DE L'ÉGLISE"));
+
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "berlin",
+                Country => "Deutschland",
+                CountryCode => "DE",
+                HouseNumber => "1",
+                Postcode => "10117",
+                Road => "unter den linden",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("Unter den Linden"));
+    assert!(text.contains("Berlin"));
+
+    // the mixed case is kept
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Toulouse",
+                Country => "France",
+                CountryCode => "FR",
+                House => "ENSEEIHT",
+                Postcode => "31000",
+                Road => "Rue Charles CAMICHEL",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("Rue Charles CAMICHEL"));
+    // only the roads and the areas are title cased
+    assert!(text.contains("ENSEEIHT"));
+
+    // the short words are not roman numerals
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "PARIS",
+                Country => "France",
+                CountryCode => "FR",
+                HouseNumber => "1",
+                Postcode => "75004",
+                Road => "AVENUE LI MIX",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("1 Avenue Li Mix"));
+
+    // the particles of the place tell its language in a multilingual country
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "BRUXELLES",
+                Country => "Belgique",
+                CountryCode => "BE",
+                HouseNumber => "16",
+                Postcode => "1000",
+                Road => "RUE DE LA LOI",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("Rue de la Loi"), "{}", text);
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "GENT",
+                Country => "België",
+                CountryCode => "BE",
+                HouseNumber => "1",
+                Postcode => "9000",
+                Road => "SINT-PIETERSPLEIN VAN DE KERK",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("Sint-Pietersplein van de Kerk"), "{}", text);
+
+    // the uppercase values of a mixed case place are kept
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "Toulouse CEDEX",
+                Country => "France",
+                CountryCode => "FR",
+                Postcode => "31042",
+                Road => "RN 7",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("RN 7"), "{}", text);
+    assert!(text.contains("Toulouse CEDEX"), "{}", text);
+
+    // the codes and the road references of an uppercase place are kept
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "SAN FRANCISCO",
+                Country => "United States of America",
+                CountryCode => "US",
+                Road => "US 101",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("US 101"), "{}", text);
+    assert!(text.contains("San Francisco"), "{}", text);
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "TAIPEI",
+                Country => "Taiwan",
+                CountryCode => "TW",
+                Postcode => "110",
+                Road => "XINYI RD",
+                State => "TPE",
+            ),
+            title_cased.clone(),
+        )
+        .unwrap();
+    assert!(text.contains("Taipei, TPE 110"), "{}", text);
+    let text = formatter
+        .format_with_config(
+            hashmap!(
+                City => "AVIGNON",
+                Country => "France",
+                CountryCode => "FR",
+                Postcode => "84000",
+                Road => "ROUTE D113",
+            ),
+            title_cased,
+        )
+        .unwrap();
+    assert!(text.contains("Route D113"), "{}", text);
+    assert!(text.contains("Avignon"), "{}", text);
+}